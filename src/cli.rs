@@ -1,35 +1,215 @@
 //! Defines the expected command-line flags and arguments.
 
-use clap::Parser;
+use std::time::Duration;
 
-use crate::consts::EXE_NAME;
+use clap::{Parser, Subcommand};
+use glob::Pattern;
+use log::LevelFilter;
+
+use sealupd::{
+    consts::EXE_NAME,
+    decompress::{ConflictPolicy, DirSync, ProtectMode, ZipEncoding},
+    download::Download,
+    exit_code::EXIT_CODE_HELP,
+    feed::Channel,
+    hook::Hook,
+    i18n::Lang,
+    log::OutputFormat,
+};
 
 /// Defines the command-line arguments and flags this program can accept.
 #[derive(Parser, Debug)]
-#[command(version)]
+#[command(
+    version,
+    after_help = EXIT_CODE_HELP,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 pub struct CliArgs {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// The update package.
-    #[arg(long, short, alias = "upgrade")]
-    pub package: String,
+    #[arg(
+        long,
+        short,
+        alias = "upgrade",
+        required_unless_present = "url",
+        conflicts_with = "url"
+    )]
+    pub package: Option<String>,
+
+    /// Download the update package from this URL instead of reading it from `--package`.
+    /// Requires `--sha256`.
+    #[arg(long, requires = "sha256", value_name = "URL")]
+    pub url: Option<String>,
+
+    /// Mirror to download from if `--url` fails. May be repeated; mirrors are tried in order.
+    #[arg(long, requires = "url", value_name = "URL")]
+    pub mirror: Vec<String>,
+
+    /// Expected SHA-256 of the downloaded package, in hexadecimal.
+    #[arg(long, requires = "url", value_name = "HEX")]
+    pub sha256: Option<String>,
+
+    /// Seconds to wait for connecting and for each response when downloading.
+    #[arg(long = "download-timeout", default_value_t = 30, value_name = "SECS")]
+    pub download_timeout: u64,
+
+    /// Seconds to wait for the body of each response when downloading, after which the download
+    /// is resumed by the next attempt.
+    #[arg(long = "download-body-timeout", default_value_t = 600, value_name = "SECS")]
+    pub download_body_timeout: u64,
+
+    /// How many more times to try each URL after a failed download, resuming where it stopped.
+    #[arg(long = "download-retries", default_value_t = 3, value_name = "COUNT")]
+    pub download_retries: usize,
 
     /// Name of the main executable in the current directory.
-    #[arg(long = "binary-name", short = 'b', alias = "binary", default_value = EXE_NAME, value_name = "FILE")]
+    #[arg(long = "binary-name", short = 'b', alias = "binary", default_value = EXE_NAME, value_name = "FILE", global = true)]
     pub binary_name: String,
 
     /// If present and not zero, wait for the process with this PID
     /// to terminate before proceeding.
-    #[arg(long)]
-    pub pid: u32,
+    #[arg(long, required = true)]
+    pub pid: Option<u32>,
 
     /// Skip launching SealDice after updating.
     #[arg(long = "skip", short)]
     pub skip_launch: bool,
 
-    /// Display more information.
+    /// Also remove the files written by the previous update that the package no longer
+    /// contains. They are moved into `backups/updater` first.
     #[arg(long)]
+    pub prune: bool,
+
+    /// Never overwrite existing files matching this glob, such as `*.yaml`. May be repeated.
+    /// `data/**` and `backups/**` are always protected.
+    #[arg(long, value_name = "GLOB", value_parser = Pattern::new)]
+    pub protect: Vec<Pattern>,
+
+    /// What to do with protected entries: skip them, or write them beside the existing
+    /// files as `<name>.new`.
+    #[arg(long = "protect-mode", value_enum, default_value_t = ProtectMode::Skip, value_name = "MODE")]
+    pub protect_mode: ProtectMode,
+
+    /// What to do when the package has a file where a directory is installed, or the other way
+    /// round: move the installed one into `backups/updater` and replace it, keep it and skip the
+    /// entries there, or abort before anything is written.
+    #[arg(long = "on-conflict", value_enum, default_value_t = ConflictPolicy::Replace, value_name = "POLICY")]
+    pub on_conflict: ConflictPolicy,
+
+    /// When to flush directories to disk after writing files into them: after each file, or
+    /// once per directory at the end of extraction, which is faster on slow storage such as
+    /// SD cards. Every file is flushed before it replaces the old one either way.
+    #[arg(long = "dir-sync", value_enum, default_value_t = DirSync::Each, value_name = "MODE")]
+    pub dir_sync: DirSync,
+
+    /// Count of threads writing the files of a zip package. Zero uses one per CPU core, and one
+    /// extracts entries one by one.
+    #[arg(long, short = 'j', default_value_t = 0, value_name = "COUNT")]
+    pub jobs: usize,
+
+    /// Code page of the file names in a zip package that are not flagged as UTF-8, as written by
+    /// archivers of Chinese Windows: cp936 (GBK) or cp437.
+    #[arg(long = "zip-encoding", value_enum, default_value_t = ZipEncoding::Cp936, value_name = "CODEPAGE")]
+    pub zip_encoding: ZipEncoding,
+
+    /// Count of backups of replaced files to keep in `backups/updater`, including the one made
+    /// by this update. Older backups are removed. Zero keeps every backup.
+    #[arg(long = "keep-backups", default_value_t = 5, value_name = "COUNT")]
+    pub keep_backups: usize,
+
+    /// Run a command at a stage of the update, given as `<STAGE>=<COMMAND>`. STAGE is one of
+    /// pre-stop, post-backup, post-extract and pre-launch. The command runs in the platform
+    /// shell; a non-zero exit code aborts the update. May be repeated.
+    #[arg(long, value_name = "STAGE=COMMAND")]
+    pub hook: Vec<Hook>,
+
+    /// Seconds each hook may run before it is killed, which aborts the update.
+    #[arg(long = "hook-timeout", default_value_t = 600, value_name = "SECS")]
+    pub hook_timeout: u64,
+
+    /// Display more information. Same as `--log-level debug`.
+    #[arg(long, global = true)]
     pub verbose: bool,
 
-    /// Produce no log file for the update.
+    /// Produce no log file for the update. Console messages are not affected.
     #[arg(long)]
     pub quiet: bool,
+
+    /// Directory in which log files are created.
+    #[arg(long = "log-dir", default_value = ".", value_name = "DIR")]
+    pub log_dir: String,
+
+    /// Most verbose level of messages written to the console and the log file,
+    /// one of off, error, warn, info, debug and trace. Overrides `--verbose`.
+    #[arg(long = "log-level", value_name = "LEVEL", global = true)]
+    pub log_level: Option<LevelFilter>,
+
+    /// Count of log files to keep in the log directory, including the current one.
+    /// Older log files are removed. Zero keeps every log file.
+    #[arg(long = "log-keep", default_value_t = 20, value_name = "COUNT")]
+    pub log_keep: usize,
+
+    /// Remove log files older than this many days. Zero keeps every log file.
+    #[arg(long = "log-max-age", default_value_t = 30, value_name = "DAYS")]
+    pub log_max_age: u64,
+
+    /// Language of the messages. Detected from the system locale if omitted.
+    #[arg(long, value_enum, value_name = "LANG", global = true)]
+    pub lang: Option<Lang>,
+
+    /// Format of the messages written to the console.
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text, value_name = "FORMAT", global = true)]
+    pub output: OutputFormat,
+}
+
+/// Commands other than updating.
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Check a release feed for a newer version than the installed one, without updating.
+    /// Exits with 100 if an update is available.
+    Check {
+        /// URL or path of the JSON release feed.
+        #[arg(long, value_name = "URL|FILE")]
+        feed: String,
+
+        /// Release channel to follow. The dev channel also receives stable releases.
+        #[arg(long, value_enum, default_value_t = Channel::Stable)]
+        channel: Channel,
+
+        /// Seconds to wait for the feed to be downloaded.
+        #[arg(long, default_value_t = 30, value_name = "SECS")]
+        timeout: u64,
+    },
+
+    /// Complete or roll back an update left unfinished by a crash or power loss, as recorded in
+    /// its journal. Updating does this first as well.
+    Recover,
+
+    /// List the backups made by previous updates in `backups/updater`, the newest first.
+    ListBackups,
+}
+
+impl CliArgs {
+    /// The download given by `--url` and the options that go with it, if any.
+    pub fn download(&self) -> Option<Download> {
+        let url = self.url.as_ref()?;
+        let mut download = Download::new(url.clone(), self.sha256.clone().unwrap_or_default())
+            .timeout(Duration::from_secs(self.download_timeout))
+            .body_timeout(Duration::from_secs(self.download_body_timeout))
+            .retries(self.download_retries);
+        download.urls.extend(self.mirror.iter().cloned());
+        Some(download)
+    }
+
+    /// The level of messages to log, as given by `--log-level`, or else implied by `--verbose`.
+    pub fn log_level(&self) -> LevelFilter {
+        match self.log_level {
+            Some(level) => level,
+            None if self.verbose => LevelFilter::Debug,
+            None => LevelFilter::Info,
+        }
+    }
 }
@@ -1,19 +1,27 @@
 use std::{env, sync::LazyLock};
 
-use clap::Parser;
-
-use crate::cli::CliArgs;
-
 /// The name of SealDice executable.
 pub const EXE_NAME: &str = if cfg!(windows) { "sealdice-core.exe" } else { "sealdice-core" };
 
 /// The name of this program.
 pub const UPDATER_NAME: &str = if cfg!(windows) { "sealupd.exe" } else { "sealupd" };
 
-/// The command-line arguments accepted from the caller.
-pub static CLI_ARGS: LazyLock<CliArgs> = LazyLock::new(|| CliArgs::parse());
+/// The name of the file, in the install root, to which the result of an update is written.
+pub const STATUS_FILE_NAME: &str = "sealupd-status.json";
+
+/// The name of the file, in the install root, recording the files written by the last update.
+pub const FILE_LIST_NAME: &str = "sealupd-files.json";
+
+/// The name of the file, in the install root, recording the steps of an unfinished update.
+pub const JOURNAL_NAME: &str = "sealupd-journal.jsonl";
+
+/// The directory, relative to the install root, into which the updater backs up files.
+pub const BACKUP_DIR: &str = "backups/updater";
+
+/// The directory, relative to the install root, into which packages given by URL are downloaded.
+pub const DOWNLOAD_DIR: &str = ".sealupd-download";
 
 /// Whether ASCII colour codes are supported by the current environment.
 /// On Windows, it checks whether the current terminal is Windows Terminal by looking for
 /// the WT_SESSION variable.
-pub const IS_ASCII_SUPPORTED: LazyLock<bool> = LazyLock::new(|| cfg!(unix) || env::var("WT_SESSION").is_ok());
+pub static IS_ASCII_SUPPORTED: LazyLock<bool> = LazyLock::new(|| cfg!(unix) || env::var("WT_SESSION").is_ok());
@@ -0,0 +1,289 @@
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use super::durable::sync_dir;
+use crate::{consts::BACKUP_DIR, status::Status};
+
+/// Name of the file describing a backup, in the directory of the backup.
+pub const BACKUP_INFO_NAME: &str = "sealupd-backup.json";
+
+/// What a backup was made for, as persisted to [`BACKUP_INFO_NAME`].
+#[derive(Serialize, Deserialize)]
+struct BackupMeta {
+    created_at: String,
+    package: String,
+    from_version: Option<String>,
+    to_version: Option<String>,
+}
+
+/// A backup found in [`BACKUP_DIR`], as listed by [`list_backups`].
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct BackupInfo {
+    /// Name of the directory of the backup, from the time it was created.
+    pub name: String,
+    /// RFC 3339 timestamp of when the backup was created.
+    pub created_at: String,
+    /// The package of the update that made the backup.
+    pub package: String,
+    /// Version of SealDice installed before the update, if it was queried.
+    pub from_version: Option<String>,
+    /// Version of SealDice in the package, as given by its manifest.
+    pub to_version: Option<String>,
+    /// Count of files in the backup.
+    pub files: usize,
+    /// Count of bytes in the files of the backup.
+    pub bytes: u64,
+}
+
+/// The directory in [`BACKUP_DIR`] into which an update moves every entry it replaces or
+/// removes, keeping their paths relative to the install root.
+#[derive(Debug, Clone)]
+pub struct BackupSet {
+    name: String,
+    dir: PathBuf,
+}
+
+impl BackupSet {
+    /// Creates a new backup in the install root, named after the current time in UTC, which
+    /// never goes back when daylight saving time ends.
+    pub fn create(root: &Path, status: &Status) -> io::Result<BackupSet> {
+        let backup_dir = root.join(BACKUP_DIR);
+        fs::create_dir_all(&backup_dir)?;
+
+        let now = Utc::now();
+        let stamp = now.format("%Y%m%d-%H%M%S").to_string();
+        let mut name = stamp.clone();
+        let mut count = 1;
+        loop {
+            match fs::create_dir(backup_dir.join(&name)) {
+                Ok(()) => break,
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                    count += 1;
+                    name = format!("{}-{}", stamp, count);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        let dir = backup_dir.join(&name);
+        let meta = BackupMeta {
+            created_at: now.to_rfc3339(),
+            package: status.package.clone(),
+            from_version: status.from_version.clone(),
+            to_version: status.to_version.clone(),
+        };
+        let mut file = File::create(dir.join(BACKUP_INFO_NAME))?;
+        serde_json::to_writer_pretty(&mut file, &meta)?;
+        file.write_all(b"\n")?;
+        file.sync_all()?;
+        sync_dir(&dir)?;
+        sync_dir(&backup_dir)?;
+
+        Ok(BackupSet { name, dir })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Path of the backup, relative to the install root.
+    pub fn rel_path(&self) -> String {
+        format!("{}/{}", BACKUP_DIR, self.name)
+    }
+
+    /// Returns the path of the entry `name` in the backup, if it has been moved there.
+    pub fn saved_path(&self, name: &str) -> Option<PathBuf> {
+        let path = self.dir.join(name);
+        path.symlink_metadata().is_ok().then_some(path)
+    }
+
+    /// Moves the entry `name` of the install root into the backup, and flushes the move to disk.
+    /// Returns false if it does not exist.
+    pub fn save(&self, root: &Path, name: &str) -> io::Result<bool> {
+        let path = root.join(name);
+        if path.symlink_metadata().is_err() {
+            return Ok(false);
+        }
+
+        let backup = self.dir.join(name);
+        if let Some(parent) = backup.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        match backup.symlink_metadata() {
+            Ok(meta) if meta.is_dir() => fs::remove_dir_all(&backup)?,
+            Ok(_) => fs::remove_file(&backup)?,
+            Err(_) => {}
+        }
+        fs::rename(&path, &backup)?;
+        for dir in [path.parent(), backup.parent()].into_iter().flatten() {
+            sync_dir(dir)?;
+        }
+        Ok(true)
+    }
+}
+
+/// Returns every backup of the install root, the newest first. Directories of [`BACKUP_DIR`]
+/// without a [`BACKUP_INFO_NAME`] are not backups and are ignored.
+pub fn list_backups(root: &Path) -> io::Result<Vec<BackupInfo>> {
+    let read_dir = match fs::read_dir(root.join(BACKUP_DIR)) {
+        Ok(read_dir) => read_dir,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+
+    let mut backups = Vec::new();
+    for entry in read_dir {
+        let entry = entry?;
+        let Ok(bytes) = fs::read(entry.path().join(BACKUP_INFO_NAME)) else {
+            continue;
+        };
+        let Ok(meta) = serde_json::from_slice::<BackupMeta>(&bytes) else {
+            continue;
+        };
+        let (files, size) = dir_size(&entry.path())?;
+        backups.push(BackupInfo {
+            name: entry.file_name().to_string_lossy().into_owned(),
+            created_at: meta.created_at,
+            package: meta.package,
+            from_version: meta.from_version,
+            to_version: meta.to_version,
+            files: files - 1,
+            bytes: size - bytes.len() as u64,
+        });
+    }
+
+    backups.sort_by(|a, b| creation_order(&b.name).cmp(&creation_order(&a.name)));
+    Ok(backups)
+}
+
+/// Orders backups by the time in their name, then by the count appended to the names of those
+/// created within the same second, where no count comes first.
+fn creation_order(name: &str) -> (&str, u32) {
+    match name.rsplit_once('-') {
+        Some((stamp, count)) if stamp.contains('-') => (stamp, count.parse().unwrap_or(u32::MAX)),
+        _ => (name, 1),
+    }
+}
+
+/// Removes the oldest backups of the install root, so that at most `keep` remain. Zero keeps
+/// every backup. The backup named `current` is never removed, even if the clock went back since
+/// the others were made, and counts towards `keep`. Upon success, return the count of backups
+/// removed.
+pub fn remove_old_backups(root: &Path, keep: usize, current: &str) -> io::Result<usize> {
+    if keep == 0 {
+        return Ok(0);
+    }
+
+    let backups = list_backups(root)?;
+    let mut count = 0;
+    for backup in backups.iter().filter(|b| b.name != current).skip(keep - 1) {
+        fs::remove_dir_all(root.join(BACKUP_DIR).join(&backup.name))?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Returns the count of files under `dir` and the sum of their sizes.
+fn dir_size(dir: &Path) -> io::Result<(usize, u64)> {
+    let mut files = 0;
+    let mut bytes = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let meta = entry.path().symlink_metadata()?;
+        if meta.is_dir() {
+            let (count, size) = dir_size(&entry.path())?;
+            files += count;
+            bytes += size;
+        } else {
+            files += 1;
+            bytes += meta.len();
+        }
+    }
+    Ok((files, bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use super::{BACKUP_INFO_NAME, BackupSet, list_backups, remove_old_backups};
+    use crate::{consts::BACKUP_DIR, status::Status};
+
+    #[test]
+    fn old_backups_are_removed_beyond_the_limit() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join(BACKUP_DIR).join("lib")).unwrap();
+
+        let mut names = Vec::new();
+        for content in ["1", "22", "333"] {
+            fs::write(root.join("sealdice-core"), content).unwrap();
+            let backup = BackupSet::create(root, &Status::new("sealdice.zip")).unwrap();
+            assert!(backup.save(root, "sealdice-core").unwrap());
+            assert!(!backup.save(root, "sealdice-core").unwrap());
+            names.push(backup.name().to_owned());
+        }
+
+        let backups = list_backups(root).unwrap();
+        assert_eq!(
+            backups.iter().map(|b| &b.name).collect::<Vec<_>>(),
+            names.iter().rev().collect::<Vec<_>>()
+        );
+        assert_eq!((backups[0].files, backups[0].bytes), (1, 3));
+
+        assert_eq!(remove_old_backups(root, 0, &names[2]).unwrap(), 0);
+        assert_eq!(remove_old_backups(root, 2, &names[2]).unwrap(), 1);
+        let backups = list_backups(root).unwrap();
+        assert_eq!(backups.len(), 2);
+        assert_eq!(backups[1].name, names[1]);
+        assert!(root.join(BACKUP_DIR).join("lib").exists());
+
+        // After the clock went back, the backup just made looks like the oldest.
+        assert_eq!(remove_old_backups(root, 1, &names[1]).unwrap(), 1);
+        assert_eq!(list_backups(root).unwrap()[0].name, names[1]);
+    }
+
+    #[test]
+    fn backups_of_the_same_second_are_ordered_by_count() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        let meta = br#"{"created_at": "", "package": "sealdice.zip", "from_version": null, "to_version": null}"#;
+        for name in [
+            "20260101-000000-10",
+            "20251231-235959",
+            "20260101-000000-2",
+            "20260101-000000",
+        ] {
+            let backup = root.join(BACKUP_DIR).join(name);
+            fs::create_dir_all(&backup).unwrap();
+            fs::write(backup.join(BACKUP_INFO_NAME), meta).unwrap();
+        }
+
+        let names = |root| {
+            list_backups(root)
+                .unwrap()
+                .into_iter()
+                .map(|b| b.name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(root),
+            [
+                "20260101-000000-10",
+                "20260101-000000-2",
+                "20260101-000000",
+                "20251231-235959"
+            ]
+        );
+        assert_eq!(remove_old_backups(root, 2, "20260101-000000-10").unwrap(), 2);
+        assert_eq!(names(root), ["20260101-000000-10", "20260101-000000-2"]);
+    }
+}
@@ -0,0 +1,229 @@
+use std::{collections::BTreeMap, fmt, path::Path};
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use super::{Package, covers_protected, install_path, is_entry_protected};
+use crate::{i18n::Msg, updater::UpdateOptions};
+
+/// What to do when the package has a file where the install root has a directory of the same
+/// name, or the other way round.
+#[derive(ValueEnum, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+    /// Move the existing file or directory into the backup, and extract the entry in its place.
+    #[default]
+    Replace,
+    /// Keep the existing file or directory, and discard the entry along with everything under it.
+    Skip,
+    /// Refuse the package before anything is written.
+    Abort,
+}
+
+/// Whether a node is a file or a directory.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeKind {
+    File,
+    Dir,
+}
+
+/// A path that is a file in the install root but a directory in the package, or the other way
+/// round.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    /// Path relative to the install root.
+    pub path: String,
+    /// What the install root has at the path.
+    pub existing: NodeKind,
+    /// Whether the path is protected or preserved, or is a directory that such entries may lie
+    /// under. It is then kept whatever the policy, since replacing it would take them along.
+    pub protected: bool,
+}
+
+impl Conflict {
+    /// What the package has at the path.
+    pub fn entry(&self) -> NodeKind {
+        match self.existing {
+            NodeKind::File => NodeKind::Dir,
+            NodeKind::Dir => NodeKind::File,
+        }
+    }
+
+    /// Whether `rel_path`, relative to the install root, is the conflicting path or lies under it.
+    pub fn covers(&self, rel_path: &str) -> bool {
+        rel_path
+            .strip_prefix(&self.path)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    }
+
+    /// How the conflict is resolved under `policy`: protected paths are skipped instead of
+    /// replaced.
+    pub fn resolved_by(&self, policy: ConflictPolicy) -> ConflictPolicy {
+        match policy {
+            ConflictPolicy::Replace if self.protected => ConflictPolicy::Skip,
+            policy => policy,
+        }
+    }
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Msg::EntryConflict(self).fmt(f)
+    }
+}
+
+/// Finds the paths of the install root whose type differs from that of the entries extracted
+/// there, including the directories implied by the paths of the entries. Protected entries are
+/// not extracted over existing files, so they cannot conflict, but the paths holding them are
+/// marked as [`Conflict::protected`]. Only the outermost conflicts are returned, sorted by path.
+pub fn find_conflicts(options: &UpdateOptions, package: &Package) -> Vec<Conflict> {
+    let root = &options.root;
+    let mut conflicts = BTreeMap::new();
+
+    for entry in package.entries.iter().filter(|e| package.is_extracted(e)) {
+        let rel_path = install_path(entry);
+        let mut end = 0;
+        for component in rel_path.split('/') {
+            end += component.len();
+            let path = &rel_path[..end];
+            end += 1;
+
+            let is_dir = end <= rel_path.len() || entry.is_dir;
+            let dest = root.join(path);
+            let existing = match exists_as(&dest) {
+                Some(existing) => existing,
+                None => break,
+            };
+            if is_dir && existing == NodeKind::File {
+                conflicts.insert(path.to_owned(), existing);
+                break;
+            }
+            if !is_dir && existing == NodeKind::Dir && !is_entry_protected(options, package, &entry.name) {
+                conflicts.insert(path.to_owned(), existing);
+            }
+        }
+    }
+
+    conflicts
+        .into_iter()
+        .map(|(path, existing)| Conflict {
+            protected: holds_kept_entries(options, package, &path, existing),
+            path,
+            existing,
+        })
+        .collect()
+}
+
+/// Whether `path` is protected or preserved, or is a directory holding entries that are.
+fn holds_kept_entries(options: &UpdateOptions, package: &Package, path: &str, existing: NodeKind) -> bool {
+    let is_dir = existing == NodeKind::Dir;
+    covers_protected(&options.protect, path, is_dir)
+        || package.manifest.as_ref().is_some_and(|manifest| {
+            manifest.is_preserved(path)
+                || (is_dir
+                    && manifest.preserve.iter().any(|p| {
+                        p.strip_prefix(path)
+                            .is_some_and(|rest| rest.starts_with('/') && rest.len() > 1)
+                    }))
+        })
+}
+
+/// What the install root has at `path`, if anything. Symbolic links to directories count as
+/// directories.
+fn exists_as(path: &Path) -> Option<NodeKind> {
+    path.symlink_metadata().ok()?;
+    Some(if path.is_dir() { NodeKind::Dir } else { NodeKind::File })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use super::{Conflict, ConflictPolicy, NodeKind, find_conflicts};
+    use crate::{
+        decompress::{Format, Manifest, Package, PackageEntry},
+        updater::UpdateOptions,
+    };
+
+    fn entry(name: &str, is_dir: bool) -> PackageEntry {
+        PackageEntry {
+            name: name.to_owned(),
+            is_dir,
+            size: 0,
+            sha256: String::new(),
+            crc32: None,
+        }
+    }
+
+    #[test]
+    fn conflicting_types_are_found_up_front() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("lib/x/y")).unwrap();
+        fs::create_dir_all(root.join("data/db")).unwrap();
+        fs::write(root.join("plugins"), b"").unwrap();
+        fs::write(root.join("docs"), b"").unwrap();
+        fs::write(root.join("a.js"), b"").unwrap();
+
+        let package = Package {
+            path: Default::default(),
+            format: Format::Zip,
+            entries: vec![
+                entry("lib", true),
+                entry("lib/x", false),
+                entry("plugins/a/b.js", false),
+                entry("docs", true),
+                entry("data/db", false),
+                entry("a.js", false),
+                entry("new/c.js", false),
+            ],
+            manifest: None,
+            patched: Vec::new(),
+            conflicts: Vec::new(),
+        };
+        let options = UpdateOptions::new("update.zip").root(root);
+
+        let conflicts = find_conflicts(&options, &package);
+        let conflict = |path: &str, existing, protected| Conflict {
+            path: path.to_owned(),
+            existing,
+            protected,
+        };
+        assert_eq!(
+            conflicts,
+            [
+                conflict("docs", NodeKind::File, false),
+                conflict("lib/x", NodeKind::Dir, false),
+                conflict("plugins", NodeKind::File, false),
+            ]
+        );
+        assert!(conflicts[2].covers("plugins/a/b.js"));
+        assert!(!conflicts[2].covers("plugins2"));
+        assert_eq!(
+            conflicts[0].resolved_by(ConflictPolicy::Replace),
+            ConflictPolicy::Replace
+        );
+
+        let package = Package {
+            entries: vec![entry("data", false), entry("lib", false)],
+            manifest: Some(Manifest {
+                preserve: vec![String::from("lib/x/y/")],
+                ..Manifest::default()
+            }),
+            ..package
+        };
+        let conflicts = find_conflicts(&options, &package);
+        assert_eq!(
+            conflicts,
+            [
+                conflict("data", NodeKind::Dir, true),
+                conflict("lib", NodeKind::Dir, true)
+            ]
+        );
+        assert_eq!(conflicts[0].resolved_by(ConflictPolicy::Replace), ConflictPolicy::Skip);
+        assert_eq!(conflicts[0].resolved_by(ConflictPolicy::Abort), ConflictPolicy::Abort);
+    }
+}
@@ -0,0 +1,150 @@
+use std::{
+    collections::BTreeSet,
+    fs::{self, File, Permissions},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use clap::ValueEnum;
+
+/// Suffix of the temporary sibling a file is written to before it is renamed into place.
+pub const TEMP_SUFFIX: &str = ".sealupd-tmp";
+
+/// When to flush to disk the directories in which files were written.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DirSync {
+    /// After each file, so that every finished file survives a crash.
+    #[default]
+    Each,
+    /// Once per directory at the end of extraction, which is faster on slow storage. A crash
+    /// before then may lose some renames, which recovery rolls back.
+    Batch,
+}
+
+/// Returns the path of the temporary sibling of `dest`.
+pub fn temp_path(dest: &Path) -> PathBuf {
+    let mut temp_name = dest.as_os_str().to_owned();
+    temp_name.push(TEMP_SUFFIX);
+    PathBuf::from(temp_name)
+}
+
+/// Writes everything read from `src` to a temporary sibling of `dest`, flushes it to disk and
+/// renames it over `dest`, so that `dest` is never left truncated or partially written. The file
+/// is given `permissions` if any, such as those of the file it replaces. The directory of `dest`
+/// still has to be synced for the rename to be durable. Upon success, return the count of bytes
+/// written.
+pub fn write_file<R: Read + ?Sized>(src: &mut R, dest: &Path, permissions: Option<&Permissions>) -> io::Result<u64> {
+    let temp = temp_path(dest);
+    let result = File::create(&temp).and_then(|mut file| {
+        let bytes = io::copy(src, &mut file)?;
+        if let Some(permissions) = permissions {
+            file.set_permissions(permissions.clone())?;
+        }
+        file.sync_all()?;
+        Ok(bytes)
+    });
+    let bytes = match result {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            _ = fs::remove_file(&temp);
+            return Err(err);
+        }
+    };
+    fs::rename(&temp, dest)?;
+    Ok(bytes)
+}
+
+/// Flushes the entries of a directory to disk, making the files created, renamed or removed in
+/// it durable.
+#[cfg(unix)]
+pub fn sync_dir(dir: &Path) -> io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+/// Directories cannot be flushed on Windows, where NTFS journals renames by itself.
+#[cfg(not(unix))]
+pub fn sync_dir(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Syncs the directories changed by an extraction, either at once or in a batch. It may be
+/// shared by the threads of an extraction.
+#[derive(Debug)]
+pub struct DirSyncer {
+    mode: DirSync,
+    pending: Mutex<BTreeSet<PathBuf>>,
+}
+
+impl DirSyncer {
+    pub fn new(mode: DirSync) -> DirSyncer {
+        DirSyncer {
+            mode,
+            pending: Mutex::new(BTreeSet::new()),
+        }
+    }
+
+    /// Records that entries were created, renamed or removed in `dir`.
+    pub fn changed(&self, dir: &Path) -> io::Result<()> {
+        match self.mode {
+            DirSync::Each => sync_dir(dir),
+            DirSync::Batch => {
+                self.pending.lock().unwrap().insert(dir.to_owned());
+                Ok(())
+            }
+        }
+    }
+
+    /// Creates `dir` and its missing parents, recording each parent in which one was created.
+    pub fn create_dir_all(&self, dir: &Path) -> io::Result<()> {
+        if dir.is_dir() {
+            return Ok(());
+        }
+        if let Some(parent) = dir.parent() {
+            self.create_dir_all(parent)?;
+        }
+        match fs::create_dir(dir) {
+            Err(err) if err.kind() != io::ErrorKind::AlreadyExists => return Err(err),
+            _ => {}
+        }
+        match dir.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => self.changed(parent),
+            _ => Ok(()),
+        }
+    }
+
+    /// Syncs every directory recorded in a batch.
+    pub fn flush(&self) -> io::Result<()> {
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap());
+        for dir in pending {
+            sync_dir(&dir)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use super::{DirSync, DirSyncer, temp_path, write_file};
+
+    #[test]
+    fn file_is_replaced_through_temporary_sibling() {
+        let dir = TempDir::new().unwrap();
+        let dest = dir.path().join("a/b/sealdice-core");
+
+        let dirs = DirSyncer::new(DirSync::Batch);
+        dirs.create_dir_all(dest.parent().unwrap()).unwrap();
+        fs::write(&dest, b"old").unwrap();
+        assert_eq!(write_file(&mut b"new".as_slice(), &dest, None).unwrap(), 3);
+        dirs.changed(dest.parent().unwrap()).unwrap();
+        assert_eq!(dirs.pending.lock().unwrap().len(), 3);
+        dirs.flush().unwrap();
+
+        assert_eq!(fs::read(&dest).unwrap(), b"new");
+        assert!(!temp_path(&dest).exists());
+    }
+}
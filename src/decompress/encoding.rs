@@ -0,0 +1,98 @@
+use clap::ValueEnum;
+
+/// Code page of the zip entry names not flagged as UTF-8. Archivers of Chinese Windows write
+/// them in the ANSI code page of the system rather than in UTF-8.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ZipEncoding {
+    /// GBK, the ANSI code page of Simplified Chinese Windows.
+    #[default]
+    #[value(alias = "gbk")]
+    Cp936,
+    /// The original IBM PC code page, which the zip format specifies.
+    Cp437,
+}
+
+/// Double-byte characters of CP936, as little-endian UTF-16 code units, zero where undefined.
+/// Rows of 190 units for lead bytes 0x81 to 0xFE hold trail bytes 0x40 to 0xFE, except 0x7F.
+static CP936_TABLE: &[u8] = include_bytes!("cp936.bin");
+
+/// Characters of CP437 from 0x80 to 0xFF. The lower half is ASCII.
+const CP437_HIGH: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å', //
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ', //
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»', //
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐', //
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧', //
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀', //
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩', //
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{a0}',
+];
+
+/// Decodes the raw name of a zip entry. Names that are valid UTF-8 are kept as is, whether or
+/// not the entry is flagged as such, since ASCII is the same in every code page and multi-byte
+/// names in a legacy code page are rarely valid UTF-8. Others are decoded from `encoding`, with
+/// undefined sequences replaced by U+FFFD.
+pub fn decode_name(raw: &[u8], encoding: ZipEncoding) -> String {
+    if let Ok(name) = std::str::from_utf8(raw) {
+        return name.to_owned();
+    }
+    match encoding {
+        ZipEncoding::Cp936 => decode_cp936(raw),
+        ZipEncoding::Cp437 => raw
+            .iter()
+            .map(|&b| if b < 0x80 { b as char } else { CP437_HIGH[b as usize - 0x80] })
+            .collect(),
+    }
+}
+
+fn decode_cp936(raw: &[u8]) -> String {
+    let mut name = String::with_capacity(raw.len() * 3 / 2);
+    let mut bytes = raw.iter().copied().peekable();
+    while let Some(lead) = bytes.next() {
+        match lead {
+            0x00..=0x7F => name.push(lead as char),
+            0x80 => name.push('€'),
+            0x81..=0xFE => match bytes.peek().copied().and_then(|trail| cp936_pair(lead, trail)) {
+                Some(c) => {
+                    bytes.next();
+                    name.push(c);
+                }
+                None => name.push(char::REPLACEMENT_CHARACTER),
+            },
+            0xFF => name.push(char::REPLACEMENT_CHARACTER),
+        }
+    }
+    name
+}
+
+/// Looks up a double-byte character of CP936.
+fn cp936_pair(lead: u8, trail: u8) -> Option<char> {
+    let column = match trail {
+        0x40..=0x7E => trail - 0x40,
+        0x80..=0xFE => trail - 0x41,
+        _ => return None,
+    };
+    let index = (lead as usize - 0x81) * 190 + column as usize;
+    let unit = u16::from_le_bytes([CP936_TABLE[index * 2], CP936_TABLE[index * 2 + 1]]);
+    match unit {
+        0 => None,
+        unit => char::from_u32(unit as u32),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ZipEncoding, decode_name};
+
+    #[test]
+    fn legacy_names_are_decoded_from_the_code_page() {
+        let gbk = b"\xc5\xc6\xb6\xd1/\xd6\xd0\xce\xc4.json";
+        assert_eq!(decode_name(gbk, ZipEncoding::Cp936), "牌堆/中文.json");
+        assert_eq!(decode_name(gbk, ZipEncoding::Cp437), "┼╞╢╤/╓╨╬─.json");
+        assert_eq!(decode_name("牌堆".as_bytes(), ZipEncoding::Cp936), "牌堆");
+        assert_eq!(
+            decode_name(b"\x80\xa1\xa1a\xff\x81", ZipEncoding::Cp936),
+            "€\u{3000}a\u{fffd}\u{fffd}"
+        );
+    }
+}
@@ -2,11 +2,67 @@ use std::{fmt, io};
 
 use zip::result::ZipError;
 
+use super::{Conflict, NameIssue};
+use crate::{exit_code::ExitCode, i18n::Msg};
+
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum DecompressError {
     IoError(io::Error),
     ZipError(ZipError),
+    SevenZError(sevenz_rust::Error),
     SlipError(String),
+    /// Entry names that cannot be extracted the same way on every platform.
+    NameError(Vec<NameIssue>),
+    /// Paths that are files in the install root but directories in the package, or the other
+    /// way round.
+    ConflictError(Vec<Conflict>),
+    /// The manifest is invalid, or the package does not match it.
+    ManifestError(String),
+    /// The package is built for another platform, given by the manifest.
+    PlatformError {
+        os: String,
+        arch: String,
+    },
+    /// The installed version is older than the minimum required by the manifest.
+    VersionError {
+        installed: String,
+        required: String,
+    },
+    /// An installed file does not match its patch, or the patched file is not as expected.
+    PatchError(String),
+}
+
+impl DecompressError {
+    /// A short, stable identifier of the error category, as reported in JSON output.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            DecompressError::IoError(_) => "io",
+            DecompressError::ZipError(_) => "zip",
+            DecompressError::SevenZError(_) => "7z",
+            DecompressError::SlipError(_) => "slip",
+            DecompressError::NameError(_) => "name",
+            DecompressError::ConflictError(_) => "conflict",
+            DecompressError::ManifestError(_) => "manifest",
+            DecompressError::PlatformError { .. } => "platform",
+            DecompressError::VersionError { .. } => "version",
+            DecompressError::PatchError(_) => "patch",
+        }
+    }
+
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            DecompressError::IoError(_) => ExitCode::DecompressIo,
+            DecompressError::ZipError(_) | DecompressError::SevenZError(_) => ExitCode::PackageCorrupt,
+            DecompressError::SlipError(_) => ExitCode::UnsafeEntry,
+            DecompressError::NameError(_) => ExitCode::InvalidName,
+            DecompressError::ConflictError(_) => ExitCode::EntryConflict,
+            DecompressError::ManifestError(_) => ExitCode::ManifestMismatch,
+            DecompressError::PlatformError { .. } => ExitCode::WrongPlatform,
+            DecompressError::VersionError { .. } => ExitCode::VersionTooOld,
+            DecompressError::PatchError(_) => ExitCode::PatchMismatch,
+        }
+    }
 }
 
 impl std::error::Error for DecompressError {}
@@ -23,14 +79,32 @@ impl From<ZipError> for DecompressError {
     }
 }
 
+impl From<sevenz_rust::Error> for DecompressError {
+    fn from(value: sevenz_rust::Error) -> Self {
+        match value {
+            sevenz_rust::Error::Io(err, _) | sevenz_rust::Error::FileOpen(err, _) => DecompressError::IoError(err),
+            err => DecompressError::SevenZError(err),
+        }
+    }
+}
+
 impl fmt::Display for DecompressError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             DecompressError::IoError(io_err) => io_err.fmt(f),
             DecompressError::ZipError(zip_err) => zip_err.fmt(f),
-            DecompressError::SlipError(entry) => {
-                write!(f, "entry '{}' might lead to slip exploit", entry.escape_debug())
-            }
+            DecompressError::SevenZError(err) => err.fmt(f),
+            DecompressError::SlipError(entry) => Msg::EntrySlip(entry).fmt(f),
+            DecompressError::NameError(issues) => Msg::NamesNotPortable(&join(issues)).fmt(f),
+            DecompressError::ConflictError(conflicts) => Msg::EntriesConflict(&join(conflicts)).fmt(f),
+            DecompressError::ManifestError(message) | DecompressError::PatchError(message) => message.fmt(f),
+            DecompressError::PlatformError { os, arch } => Msg::WrongPlatform(os, arch).fmt(f),
+            DecompressError::VersionError { installed, required } => Msg::VersionTooOld(installed, required).fmt(f),
         }
     }
 }
+
+/// Joins the items, separated by semicolons.
+fn join<T: fmt::Display>(items: &[T]) -> String {
+    items.iter().map(ToString::to_string).collect::<Vec<_>>().join("; ")
+}
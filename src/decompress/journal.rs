@@ -0,0 +1,324 @@
+use std::{
+    collections::BTreeSet,
+    fs::{self, File},
+    io::{self, Write},
+    path::Path,
+};
+
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    BACKUP_INFO_NAME, BackupSet,
+    durable::{sync_dir, temp_path},
+    patch, write_file_list,
+};
+use crate::{
+    consts::{BACKUP_DIR, JOURNAL_NAME},
+    event::Event,
+    i18n::Msg,
+    log::Logger,
+};
+
+/// One line of the journal.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "step", rename_all = "snake_case")]
+enum Step {
+    /// The update is about to modify the install root, moving what it replaces into `backup`.
+    Begin {
+        package: String,
+        backup: String,
+        started_at: String,
+    },
+    /// The file at `path` is about to be written. If it existed, it is already in the backup.
+    Write { path: String },
+    /// The file at `path` already has the content of its entry, so it is left alone.
+    Unchanged { path: String },
+    /// Every entry of the package has been written, obsolete entries removed and the
+    /// post-extract hooks have passed.
+    Extracted,
+}
+
+/// What [`recover`] did with an unfinished update.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Recovery {
+    /// No unfinished update was found.
+    Clean,
+    /// Every entry had been written, so the new release was kept.
+    Completed { files: usize },
+    /// The files written by the update were removed and the backup was moved back.
+    RolledBack { removed: usize, restored: usize },
+}
+
+/// The record, persisted to [`JOURNAL_NAME`] in the install root, of the changes an update is
+/// making. Each step is appended and flushed to disk before it is carried out, so that
+/// [`recover`] can tell what to undo after the updater was killed or the machine lost power.
+#[derive(Debug)]
+pub struct Journal {
+    file: File,
+    backup: BackupSet,
+}
+
+impl Journal {
+    /// Starts the journal of an update moving what it replaces into `backup`. Nothing may be
+    /// modified in the install root before.
+    pub fn begin(root: &Path, package: &Path, backup: BackupSet) -> io::Result<Journal> {
+        let file = File::create(root.join(JOURNAL_NAME))?;
+        let journal = Journal { file, backup };
+        journal.append(&Step::Begin {
+            package: package.to_string_lossy().into_owned(),
+            backup: journal.backup.name().to_owned(),
+            started_at: Local::now().to_rfc3339(),
+        })?;
+        sync_dir(root)?;
+        Ok(journal)
+    }
+
+    pub fn backup(&self) -> &BackupSet {
+        &self.backup
+    }
+
+    /// Moves the entry `name` of the install root into the backup, if it exists, then records
+    /// that a file is about to be written in its place.
+    pub fn prepare_write(&self, root: &Path, name: &str) -> io::Result<()> {
+        self.backup.save(root, name)?;
+        self.append(&Step::Write { path: name.to_owned() })
+    }
+
+    /// Records that the file `name` is part of the new release but left alone. It is not flushed
+    /// to disk until the next step, as it only matters once every entry has been written.
+    pub fn unchanged(&self, name: &str) -> io::Result<()> {
+        self.write(&Step::Unchanged { path: name.to_owned() })
+    }
+
+    /// Records that the new release is complete, after which [`recover`] keeps it. Until then,
+    /// a failure or a crash rolls the update back.
+    pub fn extracted(&self) -> io::Result<()> {
+        self.append(&Step::Extracted)
+    }
+
+    /// Removes the journal once the update no longer needs to be recovered.
+    pub fn finish(self, root: &Path) -> io::Result<()> {
+        drop(self.file);
+        fs::remove_file(root.join(JOURNAL_NAME))
+    }
+
+    /// Writes a step and flushes the journal to disk.
+    fn append(&self, step: &Step) -> io::Result<()> {
+        self.write(step)?;
+        self.file.sync_data()
+    }
+
+    fn write(&self, step: &Step) -> io::Result<()> {
+        let mut line = serde_json::to_vec(step)?;
+        line.push(b'\n');
+        (&self.file).write_all(&line)
+    }
+}
+
+/// Whether the journal of an unfinished update exists in the install root.
+pub fn is_unfinished(root: &Path) -> bool {
+    root.join(JOURNAL_NAME).symlink_metadata().is_ok()
+}
+
+/// Finishes an update left unfinished in the install root, as told by its journal. If every
+/// entry had been written, the new release is kept and the list of its files recorded. Otherwise,
+/// the files it wrote are removed and its backup is moved back, restoring the previous state.
+pub fn recover(root: &Path, logger: &Logger) -> io::Result<Recovery> {
+    let text = match fs::read_to_string(root.join(JOURNAL_NAME)) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Recovery::Clean),
+        Err(err) => return Err(err),
+    };
+    let steps = parse_steps(&text)?;
+
+    let recovery = match steps.first() {
+        // Nothing is modified before the first step is flushed.
+        None => Recovery::RolledBack {
+            removed: 0,
+            restored: 0,
+        },
+        Some(Step::Begin { backup, .. }) => {
+            let written = steps.iter().filter_map(|step| match step {
+                Step::Write { path } => Some(path.clone()),
+                _ => None,
+            });
+            if steps.contains(&Step::Extracted) {
+                let unchanged = steps.iter().filter_map(|step| match step {
+                    Step::Unchanged { path } => Some(path.clone()),
+                    _ => None,
+                });
+                let files: BTreeSet<String> = written.chain(unchanged).collect();
+                write_file_list(root, &files.iter().cloned().collect::<Vec<_>>())?;
+                Recovery::Completed { files: files.len() }
+            } else {
+                roll_back(root, backup, written.collect())?
+            }
+        }
+        Some(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                Msg::JournalInvalid.to_string(),
+            ));
+        }
+    };
+
+    patch::clear_staging(root)?;
+    fs::remove_file(root.join(JOURNAL_NAME))?;
+    match recovery {
+        Recovery::Completed { files } => logger.success(Msg::RecoveryCompleted(files)),
+        Recovery::RolledBack { removed, restored } => logger.success(Msg::RolledBack(removed, restored)),
+        Recovery::Clean => {}
+    }
+    logger.event(Event::Recovered { recovery });
+    Ok(recovery)
+}
+
+/// Parses every line of the journal. The last line may have been cut off by a crash, in which
+/// case it is ignored.
+fn parse_steps(text: &str) -> io::Result<Vec<Step>> {
+    let lines: Vec<&str> = text.lines().filter(|line| !line.trim().is_empty()).collect();
+    let mut steps = Vec::new();
+    for (index, line) in lines.iter().enumerate() {
+        match serde_json::from_str(line) {
+            Ok(step) => steps.push(step),
+            Err(_) if index + 1 == lines.len() => break,
+            Err(err) => return Err(io::Error::new(io::ErrorKind::InvalidData, err)),
+        }
+    }
+    Ok(steps)
+}
+
+fn roll_back(root: &Path, backup: &str, written: Vec<String>) -> io::Result<Recovery> {
+    let backup_dir = root.join(BACKUP_DIR).join(backup);
+    if !backup_dir.join(BACKUP_INFO_NAME).is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            Msg::BackupMissing(backup).to_string(),
+        ));
+    }
+
+    let mut removed = 0;
+    for name in written.iter().rev() {
+        let path = root.join(name);
+        if path.symlink_metadata().is_ok_and(|m| !m.is_dir()) {
+            fs::remove_file(&path)?;
+            removed += 1;
+        }
+        // The file may have been cut off while being written.
+        match fs::remove_file(temp_path(&path)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+    }
+
+    let mut restored = 0;
+    for entry in fs::read_dir(&backup_dir)? {
+        let entry = entry?;
+        if entry.file_name() != BACKUP_INFO_NAME {
+            restored += restore(&entry.path(), &root.join(entry.file_name()))?;
+        }
+    }
+    fs::remove_dir_all(backup_dir)?;
+
+    Ok(Recovery::RolledBack { removed, restored })
+}
+
+/// Moves `src` back to `dest`, merging directories. Returns the count of files moved.
+fn restore(src: &Path, dest: &Path) -> io::Result<usize> {
+    let is_dir = src.symlink_metadata()?.is_dir();
+    match dest.symlink_metadata() {
+        Ok(meta) if meta.is_dir() && is_dir => {
+            let mut count = 0;
+            for entry in fs::read_dir(src)? {
+                let entry = entry?;
+                count += restore(&entry.path(), &dest.join(entry.file_name()))?;
+            }
+            return Ok(count);
+        }
+        Ok(meta) if meta.is_dir() => fs::remove_dir_all(dest)?,
+        Ok(_) => fs::remove_file(dest)?,
+        Err(_) => {}
+    }
+
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(src, dest)?;
+    Ok(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use super::{Journal, Recovery, recover};
+    use crate::{
+        consts::{FILE_LIST_NAME, JOURNAL_NAME},
+        decompress::BackupSet,
+        log::Logger,
+        status::Status,
+    };
+
+    #[test]
+    fn unfinished_update_is_rolled_back() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("lib")).unwrap();
+        fs::write(root.join("sealdice-core"), b"old").unwrap();
+        fs::write(root.join("lib/a.js"), b"old a").unwrap();
+        fs::write(root.join("lib/b.js"), b"b").unwrap();
+
+        let backup = BackupSet::create(root, &Status::new("update.zip")).unwrap();
+        let journal = Journal::begin(root, "update.zip".as_ref(), backup).unwrap();
+        journal.backup().save(root, "sealdice-core").unwrap();
+        for name in ["lib/a.js", "lib/c.js"] {
+            journal.prepare_write(root, name).unwrap();
+            fs::write(root.join(name), b"new").unwrap();
+        }
+        // A crash while appending leaves a partial line.
+        drop(journal);
+        let mut text = fs::read_to_string(root.join(JOURNAL_NAME)).unwrap();
+        text.push_str(r#"{"step":"wri"#);
+        fs::write(root.join(JOURNAL_NAME), text).unwrap();
+
+        let recovery = recover(root, &Logger::new()).unwrap();
+        assert_eq!(
+            recovery,
+            Recovery::RolledBack {
+                removed: 2,
+                restored: 2
+            }
+        );
+        assert_eq!(fs::read(root.join("sealdice-core")).unwrap(), b"old");
+        assert_eq!(fs::read(root.join("lib/a.js")).unwrap(), b"old a");
+        assert_eq!(fs::read(root.join("lib/b.js")).unwrap(), b"b");
+        assert!(!root.join("lib/c.js").exists());
+        assert!(!root.join(JOURNAL_NAME).exists());
+        assert_eq!(recover(root, &Logger::new()).unwrap(), Recovery::Clean);
+    }
+
+    #[test]
+    fn extracted_update_is_completed() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        fs::write(root.join("sealdice-core"), b"old").unwrap();
+
+        let backup = BackupSet::create(root, &Status::new("update.zip")).unwrap();
+        let journal = Journal::begin(root, "update.zip".as_ref(), backup).unwrap();
+        journal.prepare_write(root, "sealdice-core").unwrap();
+        fs::write(root.join("sealdice-core"), b"new").unwrap();
+        journal.unchanged("lib/a.js").unwrap();
+        journal.extracted().unwrap();
+        drop(journal);
+
+        let recovery = recover(root, &Logger::new()).unwrap();
+        assert_eq!(recovery, Recovery::Completed { files: 2 });
+        assert_eq!(fs::read(root.join("sealdice-core")).unwrap(), b"new");
+        assert!(root.join(FILE_LIST_NAME).is_file());
+        assert!(!root.join(JOURNAL_NAME).exists());
+    }
+}
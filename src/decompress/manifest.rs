@@ -0,0 +1,242 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    env,
+};
+
+use serde::Deserialize;
+
+use super::{DecompressError, PackageEntry, Patch};
+use crate::{
+    hook::Hook,
+    i18n::Msg,
+    version::{self, Version},
+};
+
+/// The name of the manifest embedded in the root of a package.
+pub const MANIFEST_NAME: &str = "sealupd-manifest.json";
+
+/// Describes the contents of a package and the installs it applies to. Every field is optional,
+/// so that a package only needs to declare what it cares about.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct Manifest {
+    /// The version of SealDice in the package.
+    pub version: Option<String>,
+    /// The operating system the package is built for, as in `std::env::consts::OS`.
+    pub os: Option<String>,
+    /// The architecture the package is built for, as in `std::env::consts::ARCH`.
+    pub arch: Option<String>,
+    /// The oldest installed version this package can be applied to.
+    pub min_previous_version: Option<String>,
+    /// Name of the main executable in the package.
+    pub binary: Option<String>,
+    /// Hex-encoded SHA-256 of every file in the package, keyed by entry name. If not empty, the
+    /// package must contain exactly these files.
+    pub files: BTreeMap<String, String>,
+    /// Entries removed from the install root by this release.
+    pub delete: Vec<String>,
+    /// Entries never overwritten if they already exist in the install root. A directory covers
+    /// every entry below it.
+    pub preserve: Vec<String>,
+    /// Binary deltas of installed files, keyed by their path in the install root.
+    pub patches: BTreeMap<String, Patch>,
+    /// Commands to run at the stages of the update, before those given on the command line.
+    pub hooks: Vec<Hook>,
+}
+
+impl Manifest {
+    /// Parses a manifest. Fails if the main executable is not a plain file name, since it is
+    /// moved into the backup and launched from the install root.
+    pub fn parse(bytes: &[u8]) -> Result<Manifest, DecompressError> {
+        let manifest: Manifest = serde_json::from_slice(bytes)
+            .map_err(|err| DecompressError::ManifestError(Msg::ManifestInvalid(&err).to_string()))?;
+        if let Some(binary) = &manifest.binary
+            && !is_file_name(binary)
+        {
+            return Err(DecompressError::SlipError(binary.clone()));
+        }
+        Ok(manifest)
+    }
+
+    /// Fails if the package is built for another operating system or architecture than the one
+    /// this program runs on.
+    pub fn check_platform(&self) -> Result<(), DecompressError> {
+        let os_matches = self.os.as_deref().is_none_or(|os| normalize_os(os) == env::consts::OS);
+        let arch_matches = self
+            .arch
+            .as_deref()
+            .is_none_or(|arch| normalize_arch(arch) == env::consts::ARCH);
+
+        if os_matches && arch_matches {
+            Ok(())
+        } else {
+            Err(DecompressError::PlatformError {
+                os: self.os.clone().unwrap_or_else(|| String::from(env::consts::OS)),
+                arch: self.arch.clone().unwrap_or_else(|| String::from(env::consts::ARCH)),
+            })
+        }
+    }
+
+    /// Fails if `installed` is older than the minimum previous version. Passes if either version
+    /// is unknown.
+    pub fn check_version(&self, installed: Option<&Version>) -> Result<(), DecompressError> {
+        let Some(required) = self.min_previous_version.as_deref() else {
+            return Ok(());
+        };
+        let required = version::parse_version(required)
+            .ok_or_else(|| DecompressError::ManifestError(Msg::MinVersionInvalid(required).to_string()))?;
+
+        match installed {
+            Some(installed) if *installed < required => Err(DecompressError::VersionError {
+                installed: installed.to_string(),
+                required: required.to_string(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails if a file listed in the manifest is missing from `entries` or has a different
+    /// digest, or if `entries` contain a file not listed. Patches and patched files are exempt.
+    /// No-op if no file is listed.
+    pub fn check_entries(&self, entries: &[PackageEntry]) -> Result<(), DecompressError> {
+        if self.files.is_empty() {
+            return Ok(());
+        }
+
+        let mut seen = HashSet::new();
+        let checked = |e: &&PackageEntry| !e.is_dir && e.name != MANIFEST_NAME && !self.is_patch_entry(&e.name);
+        for entry in entries.iter().filter(checked) {
+            let Some(expected) = self.files.get(&entry.name) else {
+                return Err(DecompressError::ManifestError(
+                    Msg::EntryNotInManifest(&entry.name).to_string(),
+                ));
+            };
+            if !expected.eq_ignore_ascii_case(&entry.sha256) {
+                return Err(DecompressError::ManifestError(
+                    Msg::EntryDigestMismatch(&entry.name).to_string(),
+                ));
+            }
+            seen.insert(entry.name.as_str());
+        }
+
+        let missing = self
+            .files
+            .keys()
+            .find(|name| !seen.contains(name.as_str()) && !self.patches.contains_key(*name));
+        match missing {
+            Some(name) => Err(DecompressError::ManifestError(Msg::EntryMissing(name).to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether the entry holds a patch rather than a file to extract.
+    pub fn is_patch_entry(&self, name: &str) -> bool {
+        self.patches
+            .values()
+            .any(|p| p.from.values().any(|entry| entry == name))
+    }
+
+    /// Whether the entry should be left alone if it already exists in the install root.
+    pub fn is_preserved(&self, name: &str) -> bool {
+        self.preserve.iter().any(|p| {
+            let p = p.trim_end_matches('/');
+            name == p || name.strip_prefix(p).is_some_and(|rest| rest.starts_with('/'))
+        })
+    }
+}
+
+/// Maps common aliases of operating systems, such as `darwin`, to `std::env::consts::OS`.
+pub(crate) fn normalize_os(os: &str) -> String {
+    match os.to_ascii_lowercase().as_str() {
+        "darwin" | "osx" => String::from("macos"),
+        "win" | "win32" | "win64" => String::from("windows"),
+        other => other.to_owned(),
+    }
+}
+
+/// Maps common aliases of architectures, such as `amd64`, to `std::env::consts::ARCH`.
+pub(crate) fn normalize_arch(arch: &str) -> String {
+    match arch.to_ascii_lowercase().as_str() {
+        "amd64" | "x64" => String::from("x86_64"),
+        "arm64" => String::from("aarch64"),
+        "386" | "i386" | "i686" => String::from("x86"),
+        other => other.to_owned(),
+    }
+}
+
+/// Whether `name` names a file directly in the install root: not empty, not `.` or `..`, and
+/// free of separators and drive prefixes.
+fn is_file_name(name: &str) -> bool {
+    !matches!(name, "" | "." | "..") && !name.contains(['/', '\\', ':', '\0'])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::{Manifest, PackageEntry};
+    use crate::{decompress::DecompressError, version::Version};
+
+    fn file(name: &str, sha256: &str) -> PackageEntry {
+        PackageEntry {
+            name: name.to_owned(),
+            is_dir: false,
+            size: 0,
+            sha256: sha256.to_owned(),
+            crc32: None,
+        }
+    }
+
+    #[test]
+    fn manifest_for_other_platform_is_rejected() {
+        let other_os = if env::consts::OS == "windows" { "linux" } else { "windows" };
+        let manifest = Manifest::parse(format!(r#"{{"os": "{}"}}"#, other_os).as_bytes()).unwrap();
+        assert!(manifest.check_platform().is_err());
+
+        let manifest = Manifest::parse(format!(r#"{{"os": "{}"}}"#, env::consts::OS).as_bytes()).unwrap();
+        assert!(manifest.check_platform().is_ok());
+    }
+
+    #[test]
+    fn binary_outside_install_root_is_rejected() {
+        for binary in ["../../x", "lib/sealdice-core", r"..\x", "/usr/bin/x", "C:x", "..", ""] {
+            let json = serde_json::json!({ "binary": binary }).to_string();
+            assert!(
+                matches!(Manifest::parse(json.as_bytes()), Err(DecompressError::SlipError(_))),
+                "{}",
+                binary
+            );
+        }
+        assert!(Manifest::parse(br#"{"binary": "sealdice-core.exe"}"#).is_ok());
+    }
+
+    #[test]
+    fn older_installed_version_is_rejected() {
+        let manifest = Manifest::parse(br#"{"min_previous_version": "1.4.0"}"#).unwrap();
+        assert!(manifest.check_version(Some(&Version::new(1, 3, 9))).is_err());
+        assert!(manifest.check_version(Some(&Version::new(1, 4, 0))).is_ok());
+        assert!(manifest.check_version(None).is_ok());
+    }
+
+    #[test]
+    fn preserved_directories_cover_their_entries() {
+        let manifest = Manifest::parse(br#"{"preserve": ["data/", "config.yaml"]}"#).unwrap();
+        assert!(manifest.is_preserved("data"));
+        assert!(manifest.is_preserved("data/default/save.db"));
+        assert!(manifest.is_preserved("config.yaml"));
+        assert!(!manifest.is_preserved("database.js"));
+    }
+
+    #[test]
+    fn entries_must_match_listed_files() {
+        let manifest = Manifest::parse(br#"{"files": {"a": "AB", "b": "cd"}}"#).unwrap();
+        assert!(manifest.check_entries(&[file("a", "ab"), file("b", "cd")]).is_ok());
+        assert!(manifest.check_entries(&[file("a", "ab")]).is_err());
+        assert!(manifest.check_entries(&[file("a", "ab"), file("b", "ef")]).is_err());
+        assert!(
+            manifest
+                .check_entries(&[file("a", "ab"), file("b", "cd"), file("c", "")])
+                .is_err()
+        );
+    }
+}
@@ -1,155 +1,783 @@
 use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
     fs::{self, File},
-    io::{self, Read, Seek},
+    io::{self, Read},
     path::{Component, Components, Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+    },
+    thread,
 };
 
 use flate2::read::GzDecoder as GzReader;
+use sha2::{Digest, Sha256};
 use tar::Archive as TarArchive;
 use zip::ZipArchive;
 
+use durable::DirSyncer;
+use parallel::{Job, JobResult};
+
 use crate::{
-    consts::{CLI_ARGS, UPDATER_NAME},
+    consts::UPDATER_NAME,
+    event::Event,
+    i18n::Msg,
     log::Logger,
+    proc::{self, RunningExecutable},
+    updater::UpdateOptions,
 };
-use error::DecompressError;
-
+pub use backup::{BACKUP_INFO_NAME, BackupInfo, BackupSet, list_backups, remove_old_backups};
+pub use conflict::{Conflict, ConflictPolicy, NodeKind, find_conflicts};
+pub use durable::{DirSync, TEMP_SUFFIX};
+pub use encoding::{ZipEncoding, decode_name};
+pub use error::DecompressError;
+pub use journal::{Journal, Recovery, is_unfinished, recover};
+pub use manifest::{MANIFEST_NAME, Manifest};
+pub(crate) use manifest::{normalize_arch, normalize_os};
+pub use names::{NameIssue, check_names};
+pub use patch::{Patch, STAGING_DIR, apply_patches};
+pub use protect::{DEFAULT_PROTECTED, ProtectMode, covers_protected, default_protected, is_protected};
+pub use prune::{obsolete_entries, prune, write_file_list};
+
+mod backup;
+mod conflict;
+mod durable;
+mod encoding;
 mod error;
+mod journal;
+mod manifest;
+mod names;
+mod parallel;
+mod patch;
+mod protect;
+mod prune;
+mod sevenz;
+
+/// Totals of a finished decompression.
+#[derive(Debug, Default, Clone)]
+pub struct Summary {
+    /// Count of entries in the package, not counting the manifest.
+    pub entries: usize,
+    /// Count of bytes written to files.
+    pub bytes: u64,
+    /// Paths of the protected entries that were not overwritten, relative to the install root.
+    pub protected: Vec<String>,
+    /// Paths of the files written, relative to the install root.
+    pub files: Vec<String>,
+    /// Count of files written where none existed.
+    pub created: usize,
+    /// Count of existing files overwritten with different content.
+    pub updated: usize,
+    /// Count of existing files with the same content as the entry. They are left alone, except
+    /// for those already moved into the backup, such as the main executable.
+    pub unchanged: usize,
+    /// Paths of the files replaced while a process was still executing them, relative to the
+    /// install root. Those processes run the old version until they are restarted.
+    pub in_use: Vec<String>,
+}
 
-pub fn backup_sealdice() -> io::Result<bool> {
-    let exe_path = Path::new(&CLI_ARGS.binary_name);
-    if !exe_path.exists() {
-        return Ok(false);
-    }
-
-    let old_name = if cfg!(windows) {
-        format!("{}.old", CLI_ARGS.binary_name)
-    } else {
-        format!("{}_old", CLI_ARGS.binary_name)
-    };
-
-    fs::rename(exe_path, &old_name)?;
-    Ok(true)
+/// An entry of a package, as listed before anything is extracted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageEntry {
+    /// Path of the entry relative to the root of the package, separated by `/`, without a
+    /// trailing separator.
+    pub name: String,
+    pub is_dir: bool,
+    /// Uncompressed size in bytes, zero for directories.
+    pub size: u64,
+    /// Hex-encoded SHA-256 of the content, empty for directories.
+    pub sha256: String,
+    /// CRC-32 of the content, as recorded in the central directory of a zip package. Absent
+    /// for directories and in tarballs.
+    pub crc32: Option<u32>,
 }
 
-/// Decompresses the package, as provided by CLI_ARGS.package, to the current directory.
-pub fn decompress(logger: &Logger) -> Result<usize, DecompressError> {
-    let package_path = Path::new(&CLI_ARGS.package);
-    let mut file = File::open(package_path)?;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Zip,
+    Tarball,
+    SevenZip,
+}
 
-    let is_zip = package_path
-        .extension()
-        .and_then(|os_str| os_str.to_str())
-        .unwrap_or("gz")
-        .eq("zip");
+/// A package whose entries have been listed and checked for unsafe paths.
+#[derive(Debug, Clone)]
+pub struct Package {
+    path: PathBuf,
+    format: Format,
+    /// Every entry of the package, in archive order, including the manifest.
+    pub entries: Vec<PackageEntry>,
+    /// The manifest embedded in the package, if any.
+    pub manifest: Option<Manifest>,
+    /// Paths of the installed files patched into the staging directory, to be moved into place
+    /// after extraction.
+    pub patched: Vec<String>,
+    /// Paths of the install root whose type differs from that of the entries extracted there,
+    /// resolved by [`UpdateOptions::conflict_policy`] during extraction.
+    pub conflicts: Vec<Conflict>,
+}
 
-    if is_zip {
-        decompress_zip(&mut file, logger)
-    } else {
-        decompress_tarball(&mut file, logger)
+impl Package {
+    /// Count of entries to install: every entry but the manifest and patches, plus every
+    /// patched file.
+    pub fn entry_count(&self) -> usize {
+        self.entries.iter().filter(|e| self.is_extracted(e)).count() + self.patched.len()
     }
-}
 
-fn make_file<R: Read>(mut src: R, dest: &Path) -> Result<(), io::Error> {
-    if let Some(parent) = dest.parent() {
-        fs::create_dir_all(parent)?;
+    /// Paths relative to the install root to which the entries are extracted or patched.
+    pub fn install_paths(&self) -> impl Iterator<Item = String> + '_ {
+        let patch_targets = self.manifest.iter().flat_map(|m| m.patches.keys().cloned());
+        self.entries
+            .iter()
+            .filter(|e| self.is_extracted(e))
+            .map(install_path)
+            .chain(patch_targets)
     }
 
-    if dest.is_dir() || dest.to_string_lossy().ends_with('/') {
-        fs::create_dir_all(dest)?;
-    } else {
-        let mut out_file = File::create(dest)?;
-        io::copy(&mut src, &mut out_file)?;
+    /// Whether the entry is extracted as is, which excludes the manifest and patches.
+    fn is_extracted(&self, entry: &PackageEntry) -> bool {
+        let is_patch = !entry.is_dir && self.manifest.as_ref().is_some_and(|m| m.is_patch_entry(&entry.name));
+        !is_manifest(entry) && !is_patch
     }
 
-    Ok(())
+    /// Reads the content of the file entries named `names`, in a single pass over the package,
+    /// since a solid 7z archive can only be decompressed from its start.
+    fn read_entries(&self, names: &BTreeSet<&str>) -> Result<BTreeMap<String, Vec<u8>>, DecompressError> {
+        // Entries are inspected in archive order, under names that may have been decoded from a
+        // legacy code page or normalized.
+        let mut wanted = BTreeMap::new();
+        for &name in names {
+            let index = self
+                .entries
+                .iter()
+                .position(|e| !e.is_dir && e.name == name)
+                .ok_or_else(|| DecompressError::ManifestError(Msg::EntryMissing(name).to_string()))?;
+            wanted.insert(index, name);
+        }
+
+        let mut contents = BTreeMap::new();
+        let mut file = File::open(&self.path)?;
+        match self.format {
+            Format::Zip => {
+                let mut archive = ZipArchive::new(&mut file)?;
+                for (&index, &name) in &wanted {
+                    let mut bytes = Vec::new();
+                    archive.by_index(index)?.read_to_end(&mut bytes)?;
+                    contents.insert(name.to_owned(), bytes);
+                }
+            }
+            Format::Tarball => {
+                let mut archive = TarArchive::new(GzReader::new(&mut file));
+                for (index, entry) in archive.entries()?.enumerate() {
+                    let mut entry = entry?;
+                    if let Some(&name) = wanted.get(&index) {
+                        let mut bytes = Vec::new();
+                        entry.read_to_end(&mut bytes)?;
+                        contents.insert(name.to_owned(), bytes);
+                        if contents.len() == wanted.len() {
+                            break;
+                        }
+                    }
+                }
+            }
+            Format::SevenZip => {
+                sevenz::for_each_entry(&self.path, |i, _, content| {
+                    if let Some(&name) = wanted.get(&i) {
+                        let mut bytes = Vec::new();
+                        content.read_to_end(&mut bytes)?;
+                        contents.insert(name.to_owned(), bytes);
+                    }
+                    Ok(())
+                })?;
+            }
+        }
+
+        if contents.len() < wanted.len() {
+            return Err(DecompressError::IoError(io::Error::new(
+                io::ErrorKind::InvalidData,
+                Msg::PackageChanged.to_string(),
+            )));
+        }
+        Ok(contents)
+    }
 }
 
-/// Upon success, return the count of entries decompressed.
-fn decompress_zip<R: Read + Seek>(mut file: R, logger: &Logger) -> Result<usize, DecompressError> {
-    let mut archive = ZipArchive::new(&mut file)?;
-    let entry_count = archive.len();
+/// Moves the main executable of the install root into the backup. Returns false if it does not
+/// exist.
+pub fn backup_sealdice(options: &UpdateOptions, backup: &BackupSet) -> io::Result<bool> {
+    backup.save(&options.root, &options.binary_name)
+}
 
-    let updater_path = Path::new(UPDATER_NAME);
+/// Returns the hex-encoded SHA-256 digest of the package.
+pub fn package_digest(package: &Path) -> io::Result<String> {
+    let file = File::open(package)?;
+    Ok(hash_reader(file)?.1)
+}
 
-    for index in 0..entry_count {
-        let mut entry = archive.by_index(index)?;
-        let entry_name = entry
-            .enclosed_name()
-            .ok_or(DecompressError::SlipError(entry.name().to_owned()))?;
+/// Encodes a digest in lowercase hexadecimal.
+fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
 
-        let mut dest = PathBuf::new();
-        if entry_name == updater_path {
-            dest.push("new-updater");
+/// Lists and hashes every entry of the package, a zip, 7z or gzipped tar archive, and parses its
+/// manifest, without writing anything. Zip entry names that are not UTF-8 are decoded from `encoding`. Fails if an entry
+/// might lead to a slip exploit, or if entry names would extract differently or fail on
+/// another platform.
+pub fn inspect(package: &Path, encoding: ZipEncoding) -> Result<Package, DecompressError> {
+    let mut file = File::open(package)?;
+    let format = format_of(package);
+
+    let mut entries = Vec::new();
+    let mut manifest = None;
+    let mut visit =
+        |name: String, is_dir: bool, crc32: Option<u32>, reader: &mut dyn Read| -> Result<(), DecompressError> {
+            let (size, sha256) = if is_dir {
+                (0, String::new())
+            } else if name == MANIFEST_NAME {
+                let mut bytes = Vec::new();
+                reader.read_to_end(&mut bytes)?;
+                manifest = Some(Manifest::parse(&bytes)?);
+                hash_reader(bytes.as_slice())?
+            } else {
+                hash_reader(reader)?
+            };
+            entries.push(PackageEntry {
+                name,
+                is_dir,
+                size,
+                sha256,
+                crc32,
+            });
+            Ok(())
+        };
+
+    match format {
+        Format::Zip => {
+            let mut archive = ZipArchive::new(&mut file)?;
+            for index in 0..archive.len() {
+                let mut entry = archive.by_index(index)?;
+                let name = checked_entry_name(&decode_name(entry.name_raw(), encoding))?;
+                let crc32 = (!entry.is_dir()).then(|| entry.crc32());
+                visit(name, entry.is_dir(), crc32, &mut entry)?;
+            }
+        }
+        Format::Tarball => {
+            let mut archive = TarArchive::new(GzReader::new(&mut file));
+            for entry in archive.entries()? {
+                let mut entry = entry?;
+                let name = checked_entry_name(&entry.path()?.to_string_lossy())?;
+                let is_dir = entry.header().entry_type().is_dir();
+                visit(name, is_dir, None, &mut entry)?;
+            }
+        }
+        Format::SevenZip => {
+            sevenz::for_each_entry(package, |_, entry, content| {
+                let name = checked_entry_name(entry.name())?;
+                let is_dir = entry.is_directory();
+                let crc32 = (entry.has_crc && !is_dir).then_some(entry.crc as u32);
+                visit(name, is_dir, crc32, content)
+            })?;
         }
-        dest.push(entry_name);
+    }
+
+    let issues = check_names(entries.iter().map(|e| e.name.as_str()));
+    if !issues.is_empty() {
+        return Err(DecompressError::NameError(issues));
+    }
 
-        logger.console_verbose(format_args!("[{}/{}] {:?}", index + 1, entry_count, dest));
-        make_file(&mut entry, &dest)?;
+    Ok(Package {
+        path: package.to_owned(),
+        format,
+        entries,
+        manifest,
+        patched: Vec::new(),
+        conflicts: Vec::new(),
+    })
+}
+
+/// Decompresses an inspected package into the install root, as provided by the options, then
+/// moves patched files into place. Every file is recorded in the journal before it is written,
+/// and an existing file is moved into its backup. The manifest and patches are not extracted.
+pub fn decompress(
+    options: &UpdateOptions,
+    package: &Package,
+    journal: &Journal,
+    logger: &Logger,
+) -> Result<Summary, DecompressError> {
+    let dirs = DirSyncer::new(options.dir_sync);
+    let mut extraction = Extraction::new(options, package, journal, logger, &dirs);
+    extraction.resolve_conflicts()?;
+
+    let mut file = File::open(&package.path)?;
+    let workers = parallel::worker_count(options.jobs);
+    match package.format {
+        Format::Zip if workers > 1 => extraction.extract_parallel(workers)?,
+        Format::Zip => {
+            let mut archive = ZipArchive::new(&mut file)?;
+            for index in 0..archive.len() {
+                let mut entry = archive.by_index(index)?;
+                extraction.extract(index, &mut entry)?;
+            }
+        }
+        Format::Tarball => {
+            let mut archive = TarArchive::new(GzReader::new(&mut file));
+            for (index, entry) in archive.entries()?.enumerate() {
+                extraction.extract(index, &mut entry?)?;
+            }
+        }
+        Format::SevenZip => {
+            sevenz::for_each_entry(&package.path, |index, _, content| extraction.extract(index, content))?;
+        }
+    }
+    for name in &package.patched {
+        extraction.install_patched(name)?;
     }
+    dirs.flush()?;
+    patch::clear_staging(&options.root)?;
 
-    Ok(entry_count)
+    Ok(extraction.summary)
 }
 
-struct SeekableTarball<R: Read + Seek> {
-    inner: R,
+/// Extracts the entries of a package, deciding what to do with each and reporting it in the
+/// order they were inspected. Files of zip packages may be written by a pool of workers.
+struct Extraction<'a> {
+    options: &'a UpdateOptions,
+    package: &'a Package,
+    journal: &'a Journal,
+    logger: &'a Logger,
+    dirs: &'a DirSyncer,
+    /// Processes executing files of the install root when extraction started.
+    running: Vec<RunningExecutable>,
+    done: usize,
+    summary: Summary,
 }
 
-impl<R: Read + Seek> SeekableTarball<R> {
-    pub fn new(inner: R) -> SeekableTarball<R> {
-        SeekableTarball { inner }
+impl<'a> Extraction<'a> {
+    fn new(
+        options: &'a UpdateOptions,
+        package: &'a Package,
+        journal: &'a Journal,
+        logger: &'a Logger,
+        dirs: &'a DirSyncer,
+    ) -> Extraction<'a> {
+        Extraction {
+            options,
+            package,
+            journal,
+            logger,
+            dirs,
+            running: proc::running_executables(&options.root),
+            done: 0,
+            summary: Summary {
+                entries: package.entry_count(),
+                ..Summary::default()
+            },
+        }
     }
 
-    pub fn count(&mut self) -> io::Result<usize> {
-        let gz_decoder = GzReader::new(&mut self.inner);
-        let mut tar_archive = TarArchive::new(gz_decoder);
+    fn extract(&mut self, index: usize, reader: &mut dyn Read) -> Result<(), DecompressError> {
+        if let Some(write) = self.prepare(index)? {
+            let bytes = self.make_file(reader, &write)?;
+            self.finish(write, bytes);
+        }
+        Ok(())
+    }
 
-        let count = tar_archive.entries()?.count();
-        self.inner.seek(io::SeekFrom::Start(0))?;
+    /// Extracts the entries of a zip package, writing files across `workers` threads, each
+    /// reading the package through its own handle. Entries are still prepared and reported in
+    /// order on this thread.
+    fn extract_parallel(&mut self, workers: usize) -> Result<(), DecompressError> {
+        let (job_tx, job_rx) = mpsc::channel();
+        let (result_tx, result_rx) = mpsc::channel();
+        let job_rx = Mutex::new(job_rx);
+        let stop = AtomicBool::new(false);
+
+        thread::scope(|scope| {
+            for _ in 0..workers {
+                let (path, job_rx, result_tx, dirs, stop) =
+                    (&self.package.path, &job_rx, result_tx.clone(), self.dirs, &stop);
+                scope.spawn(move || parallel::work(path, job_rx, result_tx, dirs, stop));
+            }
+            drop(result_tx);
+            let result = self.queue_entries(job_tx, &result_rx);
+            if result.is_err() {
+                stop.store(true, Ordering::Relaxed);
+            }
+            result
+        })
+    }
+
+    /// Prepares every entry and queues its file for the workers, then reports the entries in
+    /// order as their files are written.
+    fn queue_entries(&mut self, jobs: Sender<Job>, results: &Receiver<JobResult>) -> Result<(), DecompressError> {
+        // Prepared entries not reported yet, with the count of bytes written once known. The first
+        // one was queued as `first`.
+        let mut pending: VecDeque<(Write, Option<u64>)> = VecDeque::new();
+        let mut first = 0;
+
+        for index in 0..self.package.entries.len() {
+            let Some(write) = self.prepare(index)? else {
+                continue;
+            };
+            if write.is_dir {
+                self.dirs.create_dir_all(&write.dest)?;
+                pending.push_back((write, Some(0)));
+            } else {
+                if let Some(parent) = write.dest.parent() {
+                    self.dirs.create_dir_all(parent)?;
+                }
+                let job = Job {
+                    seq: first + pending.len(),
+                    index,
+                    dest: write.dest.clone(),
+                    permissions: write.permissions.clone(),
+                };
+                jobs.send(job).map_err(|_| stopped())?;
+                pending.push_back((write, None));
+            }
+            while let Ok(result) = results.try_recv() {
+                self.receive(result, &mut pending, &mut first)?;
+            }
+            self.report_ready(&mut pending, &mut first);
+        }
 
-        Ok(count)
+        drop(jobs);
+        while !pending.is_empty() {
+            let result = results.recv().map_err(|_| stopped())?;
+            self.receive(result, &mut pending, &mut first)?;
+        }
+        Ok(())
     }
 
-    pub fn into_tarball(self) -> TarArchive<GzReader<R>> {
-        let gz_decoder = GzReader::new(self.inner);
-        TarArchive::new(gz_decoder)
+    /// Records the result of a job, then reports every entry ready in order.
+    fn receive(
+        &mut self,
+        (seq, result): JobResult,
+        pending: &mut VecDeque<(Write, Option<u64>)>,
+        first: &mut usize,
+    ) -> Result<(), DecompressError> {
+        let bytes = result?;
+        if let Some((_, slot)) = seq.checked_sub(*first).and_then(|pos| pending.get_mut(pos)) {
+            *slot = Some(bytes);
+        }
+        self.report_ready(pending, first);
+        Ok(())
     }
-}
 
-/// Upon success, return the count of entries decompressed.
-fn decompress_tarball<R: Read + Seek>(mut reader: R, logger: &Logger) -> Result<usize, DecompressError> {
-    let mut archive = SeekableTarball::new(&mut reader);
-    let entry_count = archive.count()?;
+    fn report_ready(&mut self, pending: &mut VecDeque<(Write, Option<u64>)>, first: &mut usize) {
+        while let Some(&(_, Some(bytes))) = pending.front() {
+            let (write, _) = pending.pop_front().unwrap();
+            *first += 1;
+            self.finish(write, bytes);
+        }
+    }
 
-    let updater_path = Path::new(UPDATER_NAME);
+    /// Decides what to do with an entry: skips it, or records it in the journal and moves the
+    /// file it replaces into the backup. Returns what is left to write, if anything.
+    fn prepare(&mut self, index: usize) -> Result<Option<Write>, DecompressError> {
+        let entry = self.package.entries.get(index).ok_or_else(|| {
+            DecompressError::IoError(io::Error::new(
+                io::ErrorKind::InvalidData,
+                Msg::PackageChanged.to_string(),
+            ))
+        })?;
+        if !self.package.is_extracted(entry) {
+            return Ok(None);
+        }
+        self.done += 1;
+
+        let mut rel_path = install_path(entry);
+        let mut dest = self.options.root.join(&rel_path);
+        if self
+            .package
+            .conflicts
+            .iter()
+            .any(|c| c.covers(&rel_path) && c.resolved_by(self.options.conflict_policy) == ConflictPolicy::Skip)
+        {
+            return Ok(None);
+        }
 
-    let mut archive = archive.into_tarball();
-    let entries = archive.entries()?;
+        if !entry.is_dir && dest.exists() && is_entry_protected(self.options, self.package, &entry.name) {
+            self.summary.protected.push(rel_path.clone());
+            match self.options.protect_mode {
+                ProtectMode::Skip => {
+                    self.logger.info(Msg::EntryProtected(&rel_path));
+                    self.logger.event(Event::EntryProtected {
+                        path: rel_path,
+                        new_path: None,
+                    });
+                    return Ok(None);
+                }
+                ProtectMode::New => {
+                    let new_path = format!("{}.new", rel_path);
+                    self.logger.info(Msg::EntryWrittenAside(&rel_path, &new_path));
+                    self.logger.event(Event::EntryProtected {
+                        path: rel_path,
+                        new_path: Some(new_path.clone()),
+                    });
+                    dest = self.options.root.join(&new_path);
+                    rel_path = new_path;
+                }
+            }
+        }
 
-    for (index, entry) in entries.enumerate() {
-        let mut entry = entry?;
-        let entry_name = entry.path()?;
+        let mut permissions = None;
+        if !entry.is_dir {
+            match dest.symlink_metadata() {
+                Ok(_) if is_unchanged(entry, &dest)? => {
+                    self.journal.unchanged(&rel_path)?;
+                    self.summary.unchanged += 1;
+                    self.summary.files.push(rel_path.clone());
+                    self.logger.verbose(Msg::EntryUnchanged(&rel_path));
+                    return Ok(None);
+                }
+                Ok(_) => {
+                    self.summary.updated += 1;
+                    // The file is moved into the backup, so its mode must be kept now.
+                    permissions = file_permissions(&dest);
+                }
+                // The main executable, or a path replaced because of a conflict, is already in
+                // the backup. It still has to be written, but it was not created.
+                Err(_) => match self.journal.backup().saved_path(&rel_path) {
+                    Some(saved) => {
+                        if is_unchanged(entry, &saved)? {
+                            self.summary.unchanged += 1;
+                        } else {
+                            self.summary.updated += 1;
+                        }
+                        permissions = file_permissions(&saved);
+                    }
+                    None => self.summary.created += 1,
+                },
+            }
+            self.check_in_use(&rel_path, &dest);
+            self.journal.prepare_write(&self.options.root, &rel_path)?;
+        }
+        Ok(Some(Write {
+            rel_path,
+            dest,
+            is_dir: entry.is_dir,
+            permissions,
+            done: self.done,
+        }))
+    }
+
+    /// Reports an entry once it has been written.
+    fn finish(&mut self, write: Write, bytes: u64) {
+        if !write.is_dir {
+            self.summary.files.push(write.rel_path.clone());
+        }
+        self.report(write.done, write.rel_path, bytes);
+    }
+
+    /// Moves each path of the install root whose type conflicts with the package into the
+    /// backup, or keeps it and logs the entries to be discarded, as told by the options. Paths
+    /// holding protected entries are always kept.
+    fn resolve_conflicts(&mut self) -> Result<(), DecompressError> {
+        for conflict in &self.package.conflicts {
+            let policy = conflict.resolved_by(self.options.conflict_policy);
+            match policy {
+                ConflictPolicy::Replace => {
+                    self.journal.prepare_write(&self.options.root, &conflict.path)?;
+                    self.logger.warn(Msg::ConflictReplaced(conflict));
+                }
+                ConflictPolicy::Skip if conflict.protected => self.logger.warn(Msg::ConflictProtected(conflict)),
+                ConflictPolicy::Skip => self.logger.warn(Msg::ConflictSkipped(conflict)),
+                ConflictPolicy::Abort => return Err(DecompressError::ConflictError(self.package.conflicts.clone())),
+            }
+            self.logger.event(Event::ConflictResolved {
+                path: conflict.path.clone(),
+                existing: conflict.existing,
+                policy,
+            });
+        }
+        Ok(())
+    }
 
-        if is_suspicious_path(entry_name.components()) {
-            let p = String::from(entry_name.to_string_lossy());
-            return Err(DecompressError::SlipError(p));
+    fn install_patched(&mut self, name: &str) -> Result<(), DecompressError> {
+        self.done += 1;
+        self.summary.updated += 1;
+        self.check_in_use(name, &self.options.root.join(name));
+        self.journal.prepare_write(&self.options.root, name)?;
+        let parent = self.options.root.join(name).parent().map(Path::to_owned);
+        if let Some(parent) = &parent {
+            self.dirs.create_dir_all(parent)?;
+        }
+        let bytes = patch::install_patched(&self.options.root, name)?;
+        if let Some(parent) = &parent {
+            self.dirs.changed(parent)?;
         }
+        self.summary.files.push(name.to_owned());
+        self.report(self.done, name.to_owned(), bytes);
+        Ok(())
+    }
 
-        let mut dest = PathBuf::new();
-        if entry_name == updater_path {
-            dest.push("new-updater");
+    /// Reports the processes still executing the file about to be replaced.
+    fn check_in_use(&mut self, rel_path: &str, dest: &Path) {
+        if self.running.is_empty() {
+            return;
+        }
+        let Ok(dest) = fs::canonicalize(dest) else {
+            return;
+        };
+
+        let mut in_use = false;
+        for process in self.running.iter().filter(|p| p.exe == dest) {
+            self.logger.warn(Msg::EntryInUse(rel_path, &process.name, process.pid));
+            self.logger.event(Event::EntryInUse {
+                path: rel_path.to_owned(),
+                pid: process.pid,
+                process: process.name.clone(),
+            });
+            in_use = true;
+        }
+        if in_use {
+            self.summary.in_use.push(rel_path.to_owned());
         }
-        dest.push(entry_name);
+    }
+
+    /// Creates a directory, or writes a file durably. Upon success, return the count of bytes
+    /// written, which is zero for directories.
+    fn make_file(&self, src: &mut dyn Read, write: &Write) -> io::Result<u64> {
+        let dest = &write.dest;
+        if write.is_dir {
+            self.dirs.create_dir_all(dest)?;
+            return Ok(0);
+        }
+
+        let permissions = write.permissions.as_ref();
+        let Some(parent) = dest.parent() else {
+            return durable::write_file(src, dest, permissions);
+        };
+        self.dirs.create_dir_all(parent)?;
+        let bytes = durable::write_file(src, dest, permissions)?;
+        self.dirs.changed(parent)?;
+        Ok(bytes)
+    }
+
+    fn report(&mut self, done: usize, rel_path: String, bytes: u64) {
+        self.summary.bytes += bytes;
+        self.logger
+            .verbose(format_args!("[{}/{}] {:?}", done, self.summary.entries, rel_path));
+        self.logger.event(Event::EntryExtracted {
+            index: done,
+            total: self.summary.entries,
+            path: rel_path,
+            bytes,
+        });
+    }
+}
+
+/// What is left to do for an entry once it is prepared.
+struct Write {
+    /// Path relative to the install root, which differs from that of the entry if the entry is
+    /// written aside.
+    rel_path: String,
+    dest: PathBuf,
+    is_dir: bool,
+    /// Permissions of the file the entry replaces, if any.
+    permissions: Option<fs::Permissions>,
+    /// Position of the entry in the progress reports.
+    done: usize,
+}
+
+/// The error of an extraction whose workers stopped without reporting why.
+fn stopped() -> DecompressError {
+    DecompressError::IoError(io::Error::other(Msg::WorkersStopped.to_string()))
+}
+
+/// Whether the entry is protected by the options or preserved by the manifest.
+fn is_entry_protected(options: &UpdateOptions, package: &Package, name: &str) -> bool {
+    protect::is_protected(&options.protect, name) || package.manifest.as_ref().is_some_and(|m| m.is_preserved(name))
+}
+
+/// The format of a package, by its extension. Anything but `.zip` and `.7z` is read as a
+/// gzipped tarball.
+fn format_of(package: &Path) -> Format {
+    match package.extension().and_then(|os_str| os_str.to_str()) {
+        Some("zip") => Format::Zip,
+        Some("7z") => Format::SevenZip,
+        _ => Format::Tarball,
+    }
+}
+
+/// The updater itself cannot be overwritten while running, so it is extracted aside.
+fn install_path(entry: &PackageEntry) -> String {
+    if entry.name == UPDATER_NAME {
+        format!("new-updater/{}", entry.name)
+    } else {
+        entry.name.clone()
+    }
+}
+
+fn is_manifest(entry: &PackageEntry) -> bool {
+    !entry.is_dir && entry.name == MANIFEST_NAME
+}
 
-        logger.console_verbose(format_args!("[{}/{}] {:?}", index + 1, entry_count, dest));
-        make_file(&mut entry, &dest)?;
+/// Normalizes the raw name of an entry into its path relative to the root of the package.
+/// Archives made on Windows may separate components with `\`, which is replaced by `/` before
+/// the name is checked, so that `..\` is caught on every platform. Fails if the entry might lead
+/// to a slip exploit.
+fn checked_entry_name(raw: &str) -> Result<String, DecompressError> {
+    let name = raw.replace('\\', "/");
+    if name.contains('\0') || is_suspicious_path(Path::new(&name).components()) {
+        return Err(DecompressError::SlipError(raw.to_owned()));
     }
+    Ok(entry_name(Path::new(&name)))
+}
+
+/// Joins the normal components of an entry path with `/`.
+fn entry_name(path: &Path) -> String {
+    path.components()
+        .filter_map(|c| match c {
+            Component::Normal(name) => Some(name.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Returns the permissions of the file at `path`, if it is one.
+fn file_permissions(path: &Path) -> Option<fs::Permissions> {
+    fs::metadata(path)
+        .ok()
+        .filter(|meta| meta.is_file())
+        .map(|meta| meta.permissions())
+}
+
+/// Whether the existing file at `dest` has the same content as the file entry: the same size,
+/// then the same CRC-32 as recorded in a zip package, or else the same SHA-256.
+fn is_unchanged(entry: &PackageEntry, dest: &Path) -> io::Result<bool> {
+    let meta = dest.symlink_metadata()?;
+    if !meta.is_file() || meta.len() != entry.size {
+        return Ok(false);
+    }
+    let mut file = File::open(dest)?;
+    match entry.crc32 {
+        Some(crc32) => {
+            let mut hasher = crc32fast::Hasher::new();
+            let mut buf = [0; 64 * 1024];
+            loop {
+                match file.read(&mut buf)? {
+                    0 => break,
+                    len => hasher.update(&buf[..len]),
+                }
+            }
+            Ok(hasher.finalize() == crc32)
+        }
+        None => Ok(hash_reader(file)?.1 == entry.sha256),
+    }
+}
 
-    Ok(entry_count)
+/// Returns the count of bytes read and their hex-encoded SHA-256 digest.
+fn hash_reader<R: Read>(mut reader: R) -> io::Result<(u64, String)> {
+    let mut hasher = Sha256::new();
+    let size = io::copy(&mut reader, &mut hasher)?;
+    Ok((size, hex(&hasher.finalize())))
 }
 
 fn is_suspicious_path(components: Components) -> bool {
     components
         .into_iter()
-        .any(|c| matches!(c, Component::ParentDir | Component::RootDir))
+        .any(|c| matches!(c, Component::ParentDir | Component::RootDir | Component::Prefix(_)))
 }
@@ -0,0 +1,147 @@
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt,
+};
+
+use crate::i18n::Msg;
+
+/// Base names that Windows reserves for devices, whatever their extension.
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9", "LPT1", "LPT2",
+    "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Characters that Windows does not allow in file names, besides control characters.
+const ILLEGAL_CHARS: [char; 7] = ['<', '>', ':', '"', '|', '?', '*'];
+
+/// An entry name that cannot be extracted the same way on every platform.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum NameIssue {
+    /// Several entries have the same path, so which one is extracted last is unclear.
+    Duplicate(String),
+    /// Two paths differ only in case, so they are the same on Windows and macOS.
+    CaseCollision(String, String),
+    /// A component is a device name reserved by Windows.
+    Reserved(String),
+    /// A component ends with a dot or a space, which Windows strips.
+    TrailingDotOrSpace(String),
+    /// A component contains a character that Windows does not allow.
+    IllegalChar(String, char),
+}
+
+impl fmt::Display for NameIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NameIssue::Duplicate(name) => Msg::NameDuplicate(name).fmt(f),
+            NameIssue::CaseCollision(a, b) => Msg::NameCaseCollision(a, b).fmt(f),
+            NameIssue::Reserved(name) => Msg::NameReserved(name).fmt(f),
+            NameIssue::TrailingDotOrSpace(name) => Msg::NameTrailingDotOrSpace(name).fmt(f),
+            NameIssue::IllegalChar(name, c) => Msg::NameIllegalChar(name, *c).fmt(f),
+        }
+    }
+}
+
+/// Checks the entry names of a package, given relative to its root and separated by `/`, for
+/// names that would extract differently or fail on case-insensitive file systems or on
+/// Windows, or that appear more than once. Directories implied by the paths are checked as
+/// well. Returns every issue found, sorted.
+pub fn check_names<'a, I: IntoIterator<Item = &'a str>>(names: I) -> Vec<NameIssue> {
+    let mut issues = BTreeSet::new();
+    // Every path and parent seen so far, by its lowercase form.
+    let mut seen: HashMap<String, &str> = HashMap::new();
+    let mut entries = HashSet::new();
+
+    for name in names {
+        if !entries.insert(name) {
+            issues.insert(NameIssue::Duplicate(name.to_owned()));
+        }
+        for (end, component) in components(name) {
+            if let Some(issue) = check_component(&name[..end], component) {
+                issues.insert(issue);
+            }
+
+            let path = &name[..end];
+            match seen.get(&path.to_lowercase()) {
+                Some(&other) if other != path => {
+                    let (a, b) = if other < path { (other, path) } else { (path, other) };
+                    issues.insert(NameIssue::CaseCollision(a.to_owned(), b.to_owned()));
+                }
+                Some(_) => {}
+                None => {
+                    seen.insert(path.to_lowercase(), path);
+                }
+            }
+        }
+    }
+    issues.into_iter().collect()
+}
+
+/// Returns each component of `name`, with the offset at which the path up to it ends.
+fn components(name: &str) -> impl Iterator<Item = (usize, &str)> {
+    let mut start = 0;
+    name.split('/').map(move |component| {
+        let end = start + component.len();
+        start = end + 1;
+        (end, component)
+    })
+}
+
+fn check_component(path: &str, component: &str) -> Option<NameIssue> {
+    if let Some(c) = component
+        .chars()
+        .find(|&c| c.is_control() || ILLEGAL_CHARS.contains(&c))
+    {
+        return Some(NameIssue::IllegalChar(path.to_owned(), c));
+    }
+    if component.ends_with(['.', ' ']) {
+        return Some(NameIssue::TrailingDotOrSpace(path.to_owned()));
+    }
+    let base = component.split('.').next().unwrap_or_default().trim_end();
+    if RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(base))
+    {
+        return Some(NameIssue::Reserved(path.to_owned()));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NameIssue, check_names};
+
+    #[test]
+    fn names_failing_on_other_platforms_are_reported() {
+        let issues = check_names([
+            "Data/x",
+            "data/x",
+            "lib",
+            "LIB/a.js",
+            "aux.txt",
+            "com1",
+            "console.log",
+            "notes./a",
+            "b ",
+            "a:b",
+            "tab\t",
+            "牌堆/中文.json",
+            "牌堆/中文.json",
+        ]);
+        assert_eq!(
+            issues,
+            [
+                NameIssue::Duplicate("牌堆/中文.json".into()),
+                NameIssue::CaseCollision("Data".into(), "data".into()),
+                NameIssue::CaseCollision("Data/x".into(), "data/x".into()),
+                NameIssue::CaseCollision("LIB".into(), "lib".into()),
+                NameIssue::Reserved("aux.txt".into()),
+                NameIssue::Reserved("com1".into()),
+                NameIssue::TrailingDotOrSpace("b ".into()),
+                NameIssue::TrailingDotOrSpace("notes.".into()),
+                NameIssue::IllegalChar("a:b".into(), ':'),
+                NameIssue::IllegalChar("tab\t".into(), '\t'),
+            ]
+        );
+        assert!(check_names(["sealdice-core", "lib", "lib/a.js", "Lib.js"]).is_empty());
+    }
+}
@@ -0,0 +1,86 @@
+use std::{
+    fs::{File, Permissions},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{Receiver, Sender},
+    },
+    thread,
+};
+
+use zip::ZipArchive;
+
+use super::{DecompressError, durable, durable::DirSyncer};
+
+/// A file entry of a zip package to be written by a worker.
+#[derive(Debug)]
+pub struct Job {
+    /// Position of the job among those queued, by which its result is reported.
+    pub seq: usize,
+    /// Index of the entry in the package.
+    pub index: usize,
+    /// Where to write the entry. Its parent directory already exists.
+    pub dest: PathBuf,
+    /// Permissions of the file the entry replaces, if any.
+    pub permissions: Option<Permissions>,
+}
+
+/// The result of a job: the count of bytes written.
+pub type JobResult = (usize, Result<u64, DecompressError>);
+
+/// Count of workers to extract with, given `jobs` from the options. Zero picks one per core.
+pub fn worker_count(jobs: usize) -> usize {
+    match jobs {
+        0 => thread::available_parallelism().map_or(1, NonZeroUsize::get),
+        jobs => jobs,
+    }
+}
+
+/// Takes jobs until the queue is closed, writing each entry durably through its own handle to the
+/// package. Jobs still queued once `stop` is set are dropped without a result.
+pub fn work(
+    package: &Path,
+    jobs: &Mutex<Receiver<Job>>,
+    results: Sender<JobResult>,
+    dirs: &DirSyncer,
+    stop: &AtomicBool,
+) {
+    let mut archive = match File::open(package)
+        .map_err(DecompressError::from)
+        .and_then(|file| Ok(ZipArchive::new(file)?))
+    {
+        Ok(archive) => archive,
+        Err(err) => {
+            stop.store(true, Ordering::Relaxed);
+            _ = results.send((usize::MAX, Err(err)));
+            return;
+        }
+    };
+
+    loop {
+        // The lock is released as soon as a job is taken.
+        let job = jobs.lock().unwrap().recv();
+        let Ok(job) = job else {
+            return;
+        };
+        if stop.load(Ordering::Relaxed) {
+            continue;
+        }
+        let result = write_entry(&mut archive, &job, dirs);
+        if result.is_err() {
+            stop.store(true, Ordering::Relaxed);
+        }
+        _ = results.send((job.seq, result));
+    }
+}
+
+fn write_entry(archive: &mut ZipArchive<File>, job: &Job, dirs: &DirSyncer) -> Result<u64, DecompressError> {
+    let mut entry = archive.by_index(job.index)?;
+    let bytes = durable::write_file(&mut entry, &job.dest, job.permissions.as_ref())?;
+    if let Some(parent) = job.dest.parent() {
+        dirs.changed(parent)?;
+    }
+    Ok(bytes)
+}
@@ -0,0 +1,171 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, BufReader, Read, Write},
+    path::Path,
+};
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use super::{DecompressError, Manifest, Package, hash_reader, hex, is_suspicious_path};
+use crate::{i18n::Msg, log::Logger, updater::UpdateOptions};
+
+/// The directory, relative to the install root, into which patched files are written before
+/// they are moved into place.
+pub const STAGING_DIR: &str = ".sealupd-staging";
+
+/// A binary delta of one file of the install root, as listed in the manifest.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Patch {
+    /// Hex-encoded SHA-256 of the patched file.
+    pub sha256: String,
+    /// Entries of the package holding a zstd patch, keyed by the hex-encoded SHA-256 of the
+    /// installed file they apply to. A patch is created with `zstd --patch-from=<old> <new>`.
+    pub from: BTreeMap<String, String>,
+}
+
+/// Applies every patch listed in the manifest to the installed files, writing the results into
+/// [`STAGING_DIR`]. Nothing outside the staging directory is modified. Fails if an installed file
+/// matches no base of its patch, or if a result does not match its expected digest. Upon
+/// success, return the paths of the patched files.
+pub fn apply_patches(
+    options: &UpdateOptions,
+    package: &Package,
+    logger: &Logger,
+) -> Result<Vec<String>, DecompressError> {
+    let Some(manifest) = &package.manifest else {
+        return Ok(Vec::new());
+    };
+    if manifest.patches.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    clear_staging(&options.root)?;
+    let result = stage_patches(options, package, manifest, logger);
+    if result.is_err() {
+        _ = clear_staging(&options.root);
+    }
+    result
+}
+
+fn stage_patches(
+    options: &UpdateOptions,
+    package: &Package,
+    manifest: &Manifest,
+    logger: &Logger,
+) -> Result<Vec<String>, DecompressError> {
+    let staging = options.root.join(STAGING_DIR);
+
+    let mut sources = Vec::new();
+    for (name, patch) in &manifest.patches {
+        if is_suspicious_path(Path::new(name).components()) {
+            return Err(DecompressError::SlipError(name.clone()));
+        }
+
+        let (_, base_digest) = File::open(options.root.join(name))
+            .and_then(hash_reader)
+            .map_err(|err| DecompressError::PatchError(Msg::PatchBaseUnreadable(name, &err).to_string()))?;
+        let Some(patch_entry) = patch.from.get(&base_digest) else {
+            return Err(DecompressError::PatchError(Msg::PatchBaseUnknown(name).to_string()));
+        };
+        sources.push((name, patch, patch_entry.as_str()));
+    }
+    let patch_data = package.read_entries(&sources.iter().map(|&(_, _, entry)| entry).collect())?;
+
+    let mut patched = Vec::new();
+    for (name, patch, patch_entry) in sources {
+        let base = fs::read(options.root.join(name))
+            .map_err(|err| DecompressError::PatchError(Msg::PatchBaseUnreadable(name, &err).to_string()))?;
+        let dest = staging.join(name);
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let digest = apply_patch(&base, &patch_data[patch_entry], &dest)?;
+        // The installed file is moved into the backup, so the patched one takes its mode.
+        fs::set_permissions(&dest, fs::metadata(options.root.join(name))?.permissions())?;
+        if !digest.eq_ignore_ascii_case(&patch.sha256) {
+            return Err(DecompressError::PatchError(Msg::PatchResultMismatch(name).to_string()));
+        }
+
+        logger.verbose(Msg::EntryPatched(name));
+        patched.push(name.clone());
+    }
+
+    Ok(patched)
+}
+
+/// Moves a patched file from the staging directory into the install root. Returns its size.
+pub fn install_patched(root: &Path, name: &str) -> io::Result<u64> {
+    let dest = root.join(name);
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let staged = root.join(STAGING_DIR).join(name);
+    let size = fs::metadata(&staged)?.len();
+    fs::rename(staged, dest)?;
+    Ok(size)
+}
+
+/// Removes the staging directory and whatever is left in it.
+pub fn clear_staging(root: &Path) -> io::Result<()> {
+    match fs::remove_dir_all(root.join(STAGING_DIR)) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+/// Decodes `patch` with `base` as its dictionary into `dest`. Returns the hex-encoded SHA-256 of
+/// the result.
+fn apply_patch(base: &[u8], patch: &[u8], dest: &Path) -> io::Result<String> {
+    let mut decoder = zstd::stream::read::Decoder::with_dictionary(BufReader::new(patch), base)?;
+    // Patches of large files are made with a long window, which the decoder refuses by default.
+    // zstd caps the window at 2^30 bytes on 32-bit targets.
+    decoder.window_log_max(if cfg!(target_pointer_width = "32") { 30 } else { 31 })?;
+
+    let mut out_file = File::create(dest)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0; 64 * 1024];
+    loop {
+        let count = decoder.read(&mut buffer)?;
+        if count == 0 {
+            break;
+        }
+        hasher.update(&buffer[..count]);
+        out_file.write_all(&buffer[..count])?;
+    }
+    out_file.sync_all()?;
+
+    Ok(hex(&hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use sha2::{Digest, Sha256};
+    use tempfile::TempDir;
+
+    use super::apply_patch;
+    use crate::decompress::hex;
+
+    #[test]
+    fn patch_is_applied_against_its_base() {
+        let dir = TempDir::new().unwrap();
+        let base = b"SealDice 1.4.6 ".repeat(100);
+        let target = [b"SealDice 1.4.7 ".repeat(50), b"SealDice 1.4.6 ".repeat(50)].concat();
+        let patch = zstd::bulk::Compressor::with_dictionary(19, &base)
+            .unwrap()
+            .compress(&target)
+            .unwrap();
+
+        let dest = dir.path().join("patched");
+        let digest = apply_patch(&base, &patch, &dest).unwrap();
+        assert_eq!(digest, hex(&Sha256::digest(&target)));
+        assert_eq!(fs::read(&dest).unwrap(), target);
+
+        let wrong_base = b"SealDice 1.3.0 ".repeat(100);
+        let result = apply_patch(&wrong_base, &patch, &dest);
+        assert!(!result.is_ok_and(|d| d == digest));
+    }
+}
@@ -0,0 +1,97 @@
+use clap::ValueEnum;
+use glob::{MatchOptions, Pattern};
+
+/// Entries protected by default: user data, and backups made by SealDice or this program.
+pub const DEFAULT_PROTECTED: [&str; 2] = ["data/**", "backups/**"];
+
+/// What to do with a protected entry that already exists in the install root.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ProtectMode {
+    /// Leave the existing file alone and discard the entry.
+    #[default]
+    Skip,
+    /// Leave the existing file alone and write the entry beside it as `<name>.new`.
+    New,
+}
+
+/// Returns the patterns in [`DEFAULT_PROTECTED`].
+pub fn default_protected() -> Vec<Pattern> {
+    DEFAULT_PROTECTED
+        .iter()
+        .map(|p| Pattern::new(p).expect("default patterns are valid"))
+        .collect()
+}
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: !cfg!(windows),
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// Whether an entry, given by its `/`-separated path, matches any of the patterns. `*` does not
+/// match across separators, while `**` does.
+pub fn is_protected(patterns: &[Pattern], name: &str) -> bool {
+    patterns.iter().any(|p| p.matches_with(name, MATCH_OPTIONS))
+}
+
+/// Whether an entry is protected, or is a directory that protected entries may lie under, such as
+/// `data` for `data/**`. Removing or replacing such an entry as a whole would take them along.
+pub fn covers_protected(patterns: &[Pattern], name: &str, is_dir: bool) -> bool {
+    is_protected(patterns, name) || (is_dir && patterns.iter().any(|p| is_ancestor(p, name)))
+}
+
+/// Whether the leading components of `pattern` match those of `name`, leaving more to match
+/// below it. A `**` component matches any number of them.
+fn is_ancestor(pattern: &Pattern, name: &str) -> bool {
+    let mut globs = pattern.as_str().split('/');
+    for component in name.split('/') {
+        match globs.next() {
+            Some("**") => return true,
+            Some(glob) => {
+                if !Pattern::new(glob).is_ok_and(|glob| glob.matches_with(component, MATCH_OPTIONS)) {
+                    return false;
+                }
+            }
+            None => return false,
+        }
+    }
+    globs.next().is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use glob::Pattern;
+
+    use super::{covers_protected, default_protected, is_protected};
+
+    #[test]
+    fn protected_patterns_match_nested_entries() {
+        let mut patterns = default_protected();
+        patterns.push(Pattern::new("*.yaml").unwrap());
+
+        assert!(is_protected(&patterns, "data/default/data.db"));
+        assert!(is_protected(&patterns, "backups/updater/sealdice-core"));
+        assert!(is_protected(&patterns, "config.yaml"));
+        assert!(!is_protected(&patterns, "lib/config.yaml"));
+        assert!(!is_protected(&patterns, "database.js"));
+    }
+
+    #[test]
+    fn directories_holding_protected_entries_are_covered() {
+        let mut patterns = default_protected();
+        patterns.push(Pattern::new("lib/*/config.yaml").unwrap());
+
+        assert!(!is_protected(&patterns, "data"));
+        assert!(covers_protected(&patterns, "data", true));
+        assert!(!covers_protected(&patterns, "data", false));
+        assert!(covers_protected(&patterns, "data/default", true));
+        assert!(covers_protected(&patterns, "lib", true));
+        assert!(covers_protected(&patterns, "lib/x", true));
+        assert!(!covers_protected(&patterns, "lib/x/a", true));
+        assert!(!covers_protected(&patterns, "plugins", true));
+
+        patterns.push(Pattern::new("**/secret.txt").unwrap());
+        assert!(covers_protected(&patterns, "plugins", true));
+        assert!(!covers_protected(&patterns, "database.js", false));
+    }
+}
@@ -0,0 +1,153 @@
+use std::{
+    collections::BTreeSet,
+    fs::{self, File},
+    io::{self, Write},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{BackupSet, DecompressError, Package, covers_protected, durable::sync_dir, is_suspicious_path};
+use crate::{consts::FILE_LIST_NAME, event::Event, i18n::Msg, log::Logger, updater::UpdateOptions};
+
+/// The files written by an update, as persisted to [`FILE_LIST_NAME`].
+#[derive(Serialize, Deserialize, Default)]
+struct FileList {
+    files: BTreeSet<String>,
+}
+
+/// Returns the files recorded by the previous update, or `None` if none were recorded.
+fn read_file_list(root: &Path) -> io::Result<Option<BTreeSet<String>>> {
+    let bytes = match fs::read(root.join(FILE_LIST_NAME)) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    let list: FileList = serde_json::from_slice(&bytes)?;
+    Ok(Some(list.files))
+}
+
+/// Records the files written by this update, so that a later update can prune those its package
+/// no longer contains.
+pub fn write_file_list(root: &Path, files: &[String]) -> io::Result<()> {
+    let list = FileList {
+        files: files.iter().cloned().collect(),
+    };
+    let path = root.join(FILE_LIST_NAME);
+    let mut temp_name = path.as_os_str().to_owned();
+    temp_name.push(".tmp");
+
+    let mut file = File::create(&temp_name)?;
+    serde_json::to_writer_pretty(&mut file, &list)?;
+    file.write_all(b"\n")?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::rename(&temp_name, path)?;
+    sync_dir(root)
+}
+
+/// Returns the existing entries of the install root to remove: those in the delete list of the
+/// manifest and, if `options.prune` is set, the files recorded by the previous update that the
+/// package no longer contains. Entries of the package itself, protected entries and the
+/// directories holding them are never removed.
+pub fn obsolete_entries(
+    options: &UpdateOptions,
+    package: &Package,
+    logger: &Logger,
+) -> Result<Vec<String>, DecompressError> {
+    let mut candidates = BTreeSet::new();
+
+    if let Some(manifest) = &package.manifest {
+        for name in &manifest.delete {
+            if is_suspicious_path(Path::new(name).components()) {
+                return Err(DecompressError::SlipError(name.clone()));
+            }
+            candidates.insert(name.trim_end_matches('/').to_owned());
+        }
+    }
+    if options.prune {
+        match read_file_list(&options.root)? {
+            Some(files) => candidates.extend(
+                files
+                    .into_iter()
+                    .filter(|name| !is_suspicious_path(Path::new(name).components())),
+            ),
+            None => logger.warn(Msg::FileListMissing),
+        }
+    }
+
+    let installed: BTreeSet<String> = package.install_paths().collect();
+    Ok(candidates
+        .into_iter()
+        .filter(|name| !name.is_empty() && !installed.contains(name))
+        .filter(|name| {
+            let path = options.root.join(name);
+            path.symlink_metadata().is_ok() && !covers_protected(&options.protect, name, path.is_dir())
+        })
+        .collect())
+}
+
+/// Moves each entry into the backup, so that it can be restored. Upon success, return the count
+/// of entries removed.
+pub fn prune(options: &UpdateOptions, entries: &[String], backup: &BackupSet, logger: &Logger) -> io::Result<usize> {
+    for name in entries {
+        backup.save(&options.root, name)?;
+        logger.verbose(Msg::EntryRemoved(name));
+        logger.event(Event::EntryRemoved { path: name.clone() });
+    }
+
+    Ok(entries.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use tempfile::TempDir;
+
+    use super::{obsolete_entries, write_file_list};
+    use crate::{
+        decompress::{Format, Manifest, Package, PackageEntry},
+        log::Logger,
+        updater::UpdateOptions,
+    };
+
+    #[test]
+    fn obsolete_entries_come_from_manifest_and_file_list() {
+        let dir = TempDir::new().unwrap();
+        for name in ["a.js", "old.js", "kept.txt", "legacy/b.js", "data/x.db"] {
+            let path = dir.path().join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, b"").unwrap();
+        }
+        let recorded = ["a.js", "old.js", "gone.js", "data/x.db"].map(String::from);
+        write_file_list(dir.path(), &recorded).unwrap();
+
+        let package = Package {
+            path: PathBuf::new(),
+            format: Format::Zip,
+            entries: vec![PackageEntry {
+                name: String::from("a.js"),
+                is_dir: false,
+                size: 0,
+                sha256: String::new(),
+                crc32: None,
+            }],
+            manifest: Some(Manifest {
+                delete: vec![String::from("legacy/"), String::from("a.js")],
+                ..Manifest::default()
+            }),
+            patched: Vec::new(),
+            conflicts: Vec::new(),
+        };
+        let options = UpdateOptions::new("").root(dir.path());
+        let logger = Logger::new();
+
+        let entries = obsolete_entries(&options, &package, &logger).unwrap();
+        assert_eq!(entries, ["legacy"]);
+
+        let entries = obsolete_entries(&options.prune(true), &package, &logger).unwrap();
+        assert_eq!(entries, ["legacy", "old.js"]);
+    }
+}
@@ -0,0 +1,45 @@
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::Path,
+};
+
+use sevenz_rust::{Password, SevenZArchiveEntry, SevenZReader};
+
+use super::DecompressError;
+
+/// Calls `each` with the index, the header and the content of every entry of a 7z package, in
+/// the order they are stored, which puts directories and empty files last. Anti-items, which
+/// delete files when an archive is applied over another, are ignored. Encrypted packages are
+/// not supported.
+pub fn for_each_entry<F>(package: &Path, mut each: F) -> Result<(), DecompressError>
+where
+    F: FnMut(usize, &SevenZArchiveEntry, &mut dyn Read) -> Result<(), DecompressError>,
+{
+    let file = File::open(package)?;
+    let len = file.metadata()?.len();
+    let mut reader = SevenZReader::new(file, len, Password::empty())?;
+
+    let mut index = 0;
+    let mut failure = None;
+    reader.for_each_entries(|entry, content| {
+        if entry.is_anti_item() {
+            return Ok(true);
+        }
+        let result = each(index, entry, content).and_then(|()| {
+            // The entries of a solid block are read from a single stream, so whatever `each` left
+            // must be consumed before the next entry. This also verifies its CRC.
+            io::copy(content, &mut io::sink())?;
+            Ok(())
+        });
+        index += 1;
+        match result {
+            Ok(()) => Ok(true),
+            Err(err) => {
+                failure = Some(err);
+                Ok(false)
+            }
+        }
+    })?;
+    failure.map_or(Ok(()), Err)
+}
@@ -0,0 +1,410 @@
+//! Downloads update packages over HTTP(S), resuming interrupted transfers.
+
+use std::{
+    error::Error,
+    fmt,
+    fs::{self, File, OpenOptions},
+    io,
+    path::Path,
+    thread,
+    time::Duration,
+};
+
+use ureq::Agent;
+
+use crate::{decompress, event::Event, exit_code::ExitCode, i18n::Msg, log::Logger};
+
+/// Where and how to download the package.
+#[derive(Debug, Clone)]
+pub struct Download {
+    /// URLs of the package, tried in order until one succeeds. Later URLs are mirrors.
+    pub urls: Vec<String>,
+    /// Hex-encoded SHA-256 the downloaded package must match.
+    pub sha256: String,
+    /// Timeout for connecting and for receiving the response headers.
+    pub timeout: Duration,
+    /// Timeout for receiving the body of each response. A transfer cut off by it is resumed by
+    /// the next attempt.
+    pub body_timeout: Duration,
+    /// How many more times to try each URL after a failure, resuming from what has been
+    /// received.
+    pub retries: usize,
+}
+
+impl Download {
+    pub fn new<S: Into<String>>(url: S, sha256: S) -> Download {
+        Download {
+            urls: vec![url.into()],
+            sha256: sha256.into(),
+            timeout: Duration::from_secs(30),
+            body_timeout: Duration::from_secs(600),
+            retries: 3,
+        }
+    }
+
+    /// Adds a mirror, tried after the URLs already added.
+    pub fn mirror<S: Into<String>>(mut self, url: S) -> Download {
+        self.urls.push(url.into());
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Download {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn body_timeout(mut self, body_timeout: Duration) -> Download {
+        self.body_timeout = body_timeout;
+        self
+    }
+
+    pub fn retries(mut self, retries: usize) -> Download {
+        self.retries = retries;
+        self
+    }
+}
+
+#[derive(Debug)]
+pub enum DownloadError {
+    IoError(io::Error),
+    /// The request failed, or the server responded with an unexpected status.
+    HttpError(String),
+    /// The downloaded file does not match the expected digest.
+    ChecksumError {
+        expected: String,
+        actual: String,
+    },
+}
+
+impl DownloadError {
+    /// A short, stable identifier of the error category, as reported in JSON output.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            DownloadError::IoError(_) => "io",
+            DownloadError::HttpError(_) => "http",
+            DownloadError::ChecksumError { .. } => "checksum",
+        }
+    }
+
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            DownloadError::IoError(_) | DownloadError::HttpError(_) => ExitCode::DownloadFailed,
+            DownloadError::ChecksumError { .. } => ExitCode::ChecksumMismatch,
+        }
+    }
+}
+
+impl Error for DownloadError {}
+
+impl From<io::Error> for DownloadError {
+    fn from(value: io::Error) -> Self {
+        DownloadError::IoError(value)
+    }
+}
+
+impl fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DownloadError::IoError(err) => err.fmt(f),
+            DownloadError::HttpError(message) => message.fmt(f),
+            DownloadError::ChecksumError { expected, actual } => Msg::ChecksumMismatch(expected, actual).fmt(f),
+        }
+    }
+}
+
+/// Returns the last segment of the path of `url`, or `package` if it has none.
+pub fn file_name(url: &str) -> String {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let path = path.split_once("://").map_or(path, |(_, rest)| rest);
+    match path.split_once('/').map(|(_, path)| path.rsplit('/').next()) {
+        Some(Some(name)) if !name.is_empty() => name.to_owned(),
+        _ => String::from("package"),
+    }
+}
+
+/// Downloads the package to `dest`. Data is received into `<dest>.part`, which is resumed by
+/// later attempts and renamed to `dest` once it matches the expected digest.
+pub fn download(download: &Download, dest: &Path, logger: &Logger) -> Result<(), DownloadError> {
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut part = dest.as_os_str().to_owned();
+    part.push(".part");
+    let part = Path::new(&part);
+
+    let config = Agent::config_builder()
+        .timeout_connect(Some(download.timeout))
+        .timeout_recv_response(Some(download.timeout))
+        .timeout_recv_body(Some(download.body_timeout))
+        .http_status_as_error(false)
+        .build();
+    let agent = Agent::new_with_config(config);
+
+    let mut last_error = DownloadError::HttpError(Msg::NoDownloadUrl.to_string());
+    for url in &download.urls {
+        logger.info(Msg::Downloading(url));
+        logger.event(Event::Downloading { url: url.clone() });
+
+        for attempt in 0..=download.retries {
+            if attempt > 0 {
+                thread::sleep(Duration::from_secs(attempt as u64));
+            }
+            if let Err(err) = fetch(&agent, url, part) {
+                logger.warn(Msg::DownloadAttemptFailed(attempt + 1, download.retries + 1, &err));
+                last_error = err;
+                continue;
+            }
+
+            let bytes = fs::metadata(part)?.len();
+            let actual = decompress::package_digest(part)?;
+            if actual.eq_ignore_ascii_case(&download.sha256) {
+                fs::rename(part, dest)?;
+                logger.success(Msg::Downloaded(bytes));
+                logger.event(Event::Downloaded { bytes });
+                return Ok(());
+            }
+
+            // Resuming cannot fix a corrupt file, so the next attempt starts over.
+            fs::remove_file(part)?;
+            last_error = DownloadError::ChecksumError {
+                expected: download.sha256.to_ascii_lowercase(),
+                actual,
+            };
+            logger.warn(Msg::DownloadAttemptFailed(
+                attempt + 1,
+                download.retries + 1,
+                &last_error,
+            ));
+        }
+    }
+
+    Err(last_error)
+}
+
+/// Requests the rest of the file from `url`, appending to `part` if the server supports ranges.
+fn fetch(agent: &Agent, url: &str, part: &Path) -> Result<(), DownloadError> {
+    let offset = fs::metadata(part).map(|m| m.len()).unwrap_or(0);
+    let mut request = agent.get(url);
+    if offset > 0 {
+        request = request.header("Range", format!("bytes={}-", offset));
+    }
+    let response = request
+        .call()
+        .map_err(|err| DownloadError::HttpError(format!("{}: {}", url, err)))?;
+
+    let status = response.status().as_u16();
+    let resumed = status == 206
+        && response
+            .headers()
+            .get("Content-Range")
+            .and_then(|v| v.to_str().ok())
+            .and_then(range_start)
+            == Some(offset);
+    let mut file = match status {
+        206 if resumed => OpenOptions::new().append(true).open(part)?,
+        // The range does not continue the part file, so the whole file is requested instead.
+        206 if offset > 0 => {
+            drop(response);
+            File::create(part)?;
+            return fetch(agent, url, part);
+        }
+        200 => File::create(part)?,
+        // The part file is already complete.
+        416 if offset > 0 => return Ok(()),
+        _ => {
+            return Err(DownloadError::HttpError(Msg::UnexpectedStatus(url, status).to_string()));
+        }
+    };
+
+    let mut reader = response.into_body().into_reader();
+    io::copy(&mut reader, &mut file)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+/// Returns the first byte position of a `Content-Range` header, such as 100 for
+/// `bytes 100-199/200`.
+fn range_start(content_range: &str) -> Option<u64> {
+    let (start, _) = content_range.strip_prefix("bytes ")?.split_once('-')?;
+    start.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        thread,
+        time::Duration,
+    };
+
+    use sha2::{Digest, Sha256};
+    use tempfile::TempDir;
+
+    use super::{Download, DownloadError, download, file_name};
+    use crate::log::Logger;
+    use Reply::{Corrupt, Cut, Full, Misplaced, Stall};
+
+    /// How the server answers a request.
+    #[derive(Clone, Copy)]
+    enum Reply {
+        /// Sends the rest of the body.
+        Full,
+        /// Sends half of the rest of the body, then closes the connection.
+        Cut,
+        /// Sends half of the rest of the body, then keeps the connection open.
+        Stall,
+        /// Sends the rest of the body with its first byte changed.
+        Corrupt,
+        /// Sends the whole body, as a partial response if a range was requested.
+        Misplaced,
+    }
+
+    /// Serves `body` with one reply per request, honouring `Range`. Returns the base URL.
+    fn serve(body: &'static [u8], replies: Vec<Reply>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/sealdice.zip", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            let mut stalled = Vec::new();
+            for reply in replies {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut offset = 0;
+                let mut ranged = false;
+                for line in BufReader::new(stream.try_clone().unwrap()).lines() {
+                    let line = line.unwrap();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some(range) = line.strip_prefix("Range: bytes=") {
+                        offset = range.trim_end_matches('-').parse().unwrap();
+                        ranged = true;
+                    }
+                }
+
+                if let Reply::Misplaced = reply {
+                    offset = 0;
+                }
+                let rest = &body[offset..];
+                let status = if ranged { "206 Partial Content" } else { "200 OK" };
+                let header = format!(
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nContent-Range: bytes {}-{}/{}\r\nConnection: close\r\n\r\n",
+                    status,
+                    rest.len(),
+                    offset,
+                    body.len() - 1,
+                    body.len()
+                );
+                stream.write_all(header.as_bytes()).unwrap();
+                match reply {
+                    Reply::Full | Reply::Misplaced => stream.write_all(rest).unwrap(),
+                    Reply::Cut => stream.write_all(&rest[..rest.len() / 2]).unwrap(),
+                    Reply::Stall => {
+                        stream.write_all(&rest[..rest.len() / 2]).unwrap();
+                        stalled.push(stream);
+                    }
+                    Reply::Corrupt => {
+                        let mut rest = rest.to_vec();
+                        rest[0] ^= 1;
+                        stream.write_all(&rest).unwrap();
+                    }
+                }
+            }
+        });
+        url
+    }
+
+    fn sha256(body: &[u8]) -> String {
+        Sha256::digest(body).iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    const BODY: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+    #[test]
+    fn interrupted_download_is_resumed() {
+        let dir = TempDir::new().unwrap();
+        let dest = dir.path().join("sealdice.zip");
+        let url = serve(BODY, vec![Cut, Full]);
+
+        let options = Download::new(url, sha256(BODY)).retries(1);
+        download(&options, &dest, &Logger::new()).unwrap();
+        assert_eq!(fs::read(&dest).unwrap(), BODY);
+        assert!(!dir.path().join("sealdice.zip.part").exists());
+    }
+
+    #[test]
+    fn stalled_download_times_out_and_is_resumed() {
+        let dir = TempDir::new().unwrap();
+        let dest = dir.path().join("sealdice.zip");
+        let url = serve(BODY, vec![Stall, Full]);
+
+        let options = Download::new(url, sha256(BODY))
+            .retries(1)
+            .body_timeout(Duration::from_secs(1));
+        download(&options, &dest, &Logger::new()).unwrap();
+        assert_eq!(fs::read(&dest).unwrap(), BODY);
+    }
+
+    #[test]
+    fn corrupt_download_is_retried_from_scratch() {
+        let dir = TempDir::new().unwrap();
+        let dest = dir.path().join("sealdice.zip");
+        let url = serve(BODY, vec![Corrupt, Full]);
+
+        let options = Download::new(url, sha256(BODY)).retries(1);
+        download(&options, &dest, &Logger::new()).unwrap();
+        assert_eq!(fs::read(&dest).unwrap(), BODY);
+        assert!(!dir.path().join("sealdice.zip.part").exists());
+    }
+
+    #[test]
+    fn misplaced_range_restarts_download() {
+        let dir = TempDir::new().unwrap();
+        let dest = dir.path().join("sealdice.zip");
+        let url = serve(BODY, vec![Cut, Misplaced, Full]);
+
+        let options = Download::new(url, sha256(BODY)).retries(1);
+        download(&options, &dest, &Logger::new()).unwrap();
+        assert_eq!(fs::read(&dest).unwrap(), BODY);
+    }
+
+    #[test]
+    fn mirror_is_tried_after_failure() {
+        let dir = TempDir::new().unwrap();
+        let dest = dir.path().join("sealdice.zip");
+        let unreachable = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}/sealdice.zip", listener.local_addr().unwrap())
+        };
+        let mirror = serve(BODY, vec![Full]);
+
+        let options = Download::new(unreachable, sha256(BODY))
+            .mirror(mirror)
+            .retries(0)
+            .timeout(Duration::from_secs(5));
+        download(&options, &dest, &Logger::new()).unwrap();
+        assert_eq!(fs::read(&dest).unwrap(), BODY);
+    }
+
+    #[test]
+    fn checksum_mismatch_is_rejected() {
+        let dir = TempDir::new().unwrap();
+        let dest = dir.path().join("sealdice.zip");
+        let url = serve(BODY, vec![Full]);
+
+        let options = Download::new(url, sha256(b"something else")).retries(0);
+        let result = download(&options, &dest, &Logger::new());
+        assert!(matches!(result, Err(DownloadError::ChecksumError { .. })));
+        assert!(!dest.exists());
+    }
+
+    #[test]
+    fn file_name_is_taken_from_url_path() {
+        assert_eq!(
+            file_name("https://example.com/v1.5/sealdice.tar.gz?sig=1"),
+            "sealdice.tar.gz"
+        );
+        assert_eq!(file_name("https://example.com/"), "package");
+        assert_eq!(file_name("https://example.com"), "package");
+    }
+}
@@ -0,0 +1,145 @@
+//! Defines the structured events reported when `--output json` is specified.
+
+use serde::Serialize;
+
+use crate::{
+    decompress::{BackupInfo, ConflictPolicy, NodeKind, Recovery},
+    exit_code::ExitCode,
+    feed::Release,
+    hook::HookStage,
+};
+
+/// The stages of an update, in the order they are carried out.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    Download,
+    Wait,
+    Recover,
+    Verify,
+    #[cfg_attr(not(windows), allow(dead_code))]
+    StopMilky,
+    Backup,
+    Decompress,
+    Prune,
+    Hook,
+    Restart,
+}
+
+/// A single event of the update. Each event is serialized as one JSON object per line,
+/// tagged by its `event` field.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// Downloading the package from `url` has started.
+    Downloading { url: String },
+    /// The package has been downloaded and matches its checksum.
+    Downloaded { bytes: u64 },
+    /// Waiting for the process with the given PID to terminate.
+    Waiting { pid: u32 },
+    /// The awaited process has terminated.
+    ProcessStopped { pid: u32 },
+    /// An update left unfinished in the install root has been completed or rolled back.
+    Recovered { recovery: Recovery },
+    /// The package has been checked against its manifest, if any, before anything is written.
+    Verified {
+        /// The version of SealDice in the package, as given by the manifest.
+        version: Option<String>,
+        entries: usize,
+    },
+    /// Bundled Milky processes have been stopped.
+    #[cfg_attr(not(windows), allow(dead_code))]
+    MilkyStopped { count: usize },
+    /// The backup of this update has been created at `path`, relative to the install root, and
+    /// the main executable has been moved into it, unless it did not exist.
+    BackupDone { existed: bool, path: String },
+    /// Decompression of the package has started.
+    Decompressing { package: String },
+    /// One entry of the package has been extracted.
+    EntryExtracted {
+        index: usize,
+        total: usize,
+        path: String,
+        bytes: u64,
+    },
+    /// An entry was not extracted over an existing protected file. If `new_path` is present, it
+    /// was written there instead.
+    EntryProtected { path: String, new_path: Option<String> },
+    /// The install root has a directory where the package has a file, or the other way round.
+    /// The `existing` node was moved into the backup or kept, as told by `policy`.
+    ConflictResolved {
+        path: String,
+        existing: NodeKind,
+        policy: ConflictPolicy,
+    },
+    /// A file has been replaced while the process `pid` was executing it. The process keeps
+    /// running the old version until it is restarted.
+    EntryInUse { path: String, pid: u32, process: String },
+    /// All entries of the package have been extracted, except `protected` ones. Of the files,
+    /// `created` did not exist, `updated` were overwritten and `unchanged` were left alone since
+    /// they already had the content of the entry.
+    Decompressed {
+        entries: usize,
+        bytes: u64,
+        protected: usize,
+        created: usize,
+        updated: usize,
+        unchanged: usize,
+    },
+    /// An obsolete entry has been moved from the install root into the backup directory.
+    EntryRemoved { path: String },
+    /// Every obsolete entry has been removed.
+    Pruned { count: usize },
+    /// A hook command exited successfully.
+    HookFinished { stage: HookStage, command: String },
+    /// The main executable is about to be launched.
+    Launching { binary: String },
+    /// Every stage has completed.
+    Finished,
+    /// The update was aborted at `stage`.
+    Failed {
+        stage: Stage,
+        kind: &'static str,
+        exit_code: ExitCode,
+        message: String,
+    },
+    /// The result of the `check` subcommand, the only event it reports.
+    Checked {
+        installed: Option<String>,
+        latest: Option<String>,
+        update_available: bool,
+        /// The newest release, if any.
+        release: Option<Release>,
+    },
+    /// The result of the `list-backups` subcommand, the only event it reports. The newest backup
+    /// comes first.
+    Backups { backups: Vec<BackupInfo> },
+    /// Final summary, always the last event.
+    Summary {
+        success: bool,
+        exit_code: ExitCode,
+        entries: usize,
+        bytes: u64,
+        elapsed_ms: u64,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Event, Stage};
+    use crate::exit_code::ExitCode;
+
+    #[test]
+    fn events_are_tagged_in_snake_case() {
+        let event = Event::Failed {
+            stage: Stage::StopMilky,
+            kind: "io",
+            exit_code: ExitCode::StopMilkyFailed,
+            message: String::from("denied"),
+        };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"event":"failed","stage":"stop_milky","kind":"io","exit_code":11,"message":"denied"}"#
+        );
+    }
+}
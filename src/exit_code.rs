@@ -0,0 +1,129 @@
+//! Defines the exit codes of this program, one per failure category.
+
+use serde::Serialize;
+
+/// The exit code of this program. Codes are grouped by stage in steps of ten, so that callers
+/// can tell transient failures, after which retrying later may help, from broken packages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExitCode {
+    /// The update finished.
+    Success = 0,
+    /// The process given by `--pid` was still running after waiting.
+    WaitTimeout = 10,
+    /// The bundled Milky processes could not be stopped.
+    #[cfg_attr(not(windows), allow(dead_code))]
+    StopMilkyFailed = 11,
+    /// The backup could not be created, or the main executable could not be moved into it.
+    /// Also reported when `list-backups` cannot read the backups.
+    BackupFailed = 20,
+    /// An update left unfinished could not be completed or rolled back.
+    RecoverFailed = 21,
+    /// The package could not be read, or an entry could not be written.
+    DecompressIo = 30,
+    /// The package is not a valid archive.
+    PackageCorrupt = 31,
+    /// The package contains an entry that would be written outside the current directory.
+    UnsafeEntry = 32,
+    /// The package does not match its manifest, or the manifest is invalid.
+    ManifestMismatch = 33,
+    /// The package is built for another operating system or architecture.
+    WrongPlatform = 34,
+    /// The installed version is too old for the package.
+    VersionTooOld = 35,
+    /// An obsolete entry could not be moved into the backup directory.
+    PruneFailed = 36,
+    /// An installed file does not match the patch package.
+    PatchMismatch = 37,
+    /// The package contains entry names that differ only in case, or that Windows does not allow.
+    InvalidName = 38,
+    /// The package has a file where the install has a directory, or the other way round, and
+    /// `--on-conflict abort` was given.
+    EntryConflict = 39,
+    /// The main executable could not be launched.
+    RestartFailed = 40,
+    /// A hook command could not be run or exited with a non-zero code.
+    HookFailed = 50,
+    /// The package could not be downloaded from any URL.
+    DownloadFailed = 60,
+    /// The downloaded package does not match the expected checksum.
+    ChecksumMismatch = 61,
+    /// `check` could not read the release feed or the installed version.
+    CheckFailed = 70,
+    /// `check` found a newer release than the installed version.
+    UpdateAvailable = 100,
+}
+
+/// The text appended to `--help`, listing every exit code.
+pub const EXIT_CODE_HELP: &str = "\
+Exit codes:
+  0   The update finished
+  10  The process given by --pid was still running after waiting; retry later
+  11  The bundled Milky processes could not be stopped; retry later
+  20  The main executable could not be backed up, or backups could not be listed
+  21  An unfinished update could not be recovered; restore it from backups/updater
+  30  The package could not be read, or an entry could not be written
+  31  The package is not a valid archive; download it again
+  32  The package contains an unsafe entry; download it again
+  33  The package does not match its manifest; download it again
+  34  The package is built for another operating system or architecture
+  35  The installed version is too old for the package; update step by step
+  36  An obsolete file could not be removed; it may be in use
+  37  The installed files do not match the patch package; use the full package
+  38  The package contains entry names that do not work on every platform; rebuild it
+  39  A file of the package is a directory in the install, or the other way round
+  40  The main executable could not be launched
+  50  A hook command failed; see its output in the log
+  60  The package could not be downloaded; check the network and retry later
+  61  The downloaded package does not match the expected checksum
+  70  check: the release feed or the installed version could not be read
+  100 check: an update is available";
+
+impl ExitCode {
+    #[cfg(test)]
+    pub const ALL: [ExitCode; 21] = [
+        ExitCode::Success,
+        ExitCode::WaitTimeout,
+        ExitCode::StopMilkyFailed,
+        ExitCode::BackupFailed,
+        ExitCode::RecoverFailed,
+        ExitCode::DecompressIo,
+        ExitCode::PackageCorrupt,
+        ExitCode::UnsafeEntry,
+        ExitCode::ManifestMismatch,
+        ExitCode::WrongPlatform,
+        ExitCode::VersionTooOld,
+        ExitCode::PruneFailed,
+        ExitCode::PatchMismatch,
+        ExitCode::InvalidName,
+        ExitCode::EntryConflict,
+        ExitCode::RestartFailed,
+        ExitCode::HookFailed,
+        ExitCode::DownloadFailed,
+        ExitCode::ChecksumMismatch,
+        ExitCode::CheckFailed,
+        ExitCode::UpdateAvailable,
+    ];
+
+    pub fn code(self) -> i32 {
+        self as i32
+    }
+}
+
+impl Serialize for ExitCode {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i32(self.code())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EXIT_CODE_HELP, ExitCode};
+
+    #[test]
+    fn every_exit_code_is_documented() {
+        for code in ExitCode::ALL {
+            let line = format!("\n  {:<4}", code.code());
+            assert!(EXIT_CODE_HELP.contains(&line), "exit code {:?} is not documented", code);
+        }
+    }
+}
@@ -0,0 +1,175 @@
+//! Reads release feeds and tells whether a newer SealDice is available.
+
+use std::{env, fmt, fs, io, path::Path, time::Duration};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use ureq::Agent;
+
+use crate::{
+    decompress::{normalize_arch, normalize_os},
+    i18n::Msg,
+    version::{self, Version},
+};
+
+/// The release channels, from the most to the least stable.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    #[default]
+    Stable,
+    Dev,
+}
+
+/// A package of one release, for one platform.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Release {
+    pub version: String,
+    #[serde(default)]
+    pub channel: Channel,
+    /// The operating system the package is built for, as in `std::env::consts::OS`.
+    pub os: String,
+    /// The architecture the package is built for, as in `std::env::consts::ARCH`.
+    pub arch: String,
+    pub url: String,
+    /// Hex-encoded SHA-256 of the package.
+    pub sha256: String,
+    #[serde(default)]
+    pub mirrors: Vec<String>,
+}
+
+/// A release feed, listing the packages of every release in any order.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Feed {
+    pub releases: Vec<Release>,
+}
+
+impl Feed {
+    pub fn parse(text: &str) -> Result<Feed, FeedError> {
+        serde_json::from_str(text).map_err(|err| FeedError(Msg::FeedInvalid(&err).to_string()))
+    }
+
+    /// Reads the feed from a URL starting with `http://` or `https://`, or else from a file.
+    pub fn load(source: &str, timeout: Duration) -> Result<Feed, FeedError> {
+        let text = if source.starts_with("http://") || source.starts_with("https://") {
+            let config = Agent::config_builder().timeout_global(Some(timeout)).build();
+            Agent::new_with_config(config)
+                .get(source)
+                .call()
+                .and_then(|mut response| response.body_mut().read_to_string())
+                .map_err(|err| FeedError(format!("{}: {}", source, err)))?
+        } else {
+            fs::read_to_string(source).map_err(|err| FeedError(format!("{}: {}", source, err)))?
+        };
+        Feed::parse(&text)
+    }
+
+    /// Returns the newest release for this platform in `channel`. The dev channel also receives
+    /// stable releases.
+    pub fn latest(&self, channel: Channel) -> Option<(Version, &Release)> {
+        self.releases
+            .iter()
+            .filter(|r| r.channel <= channel)
+            .filter(|r| normalize_os(&r.os) == env::consts::OS && normalize_arch(&r.arch) == env::consts::ARCH)
+            .filter_map(|r| Some((version::parse_version(&r.version)?, r)))
+            .max_by(|(a, _), (b, _)| a.cmp(b))
+    }
+}
+
+#[derive(Debug)]
+pub struct FeedError(String);
+
+impl std::error::Error for FeedError {}
+
+impl fmt::Display for FeedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl From<io::Error> for FeedError {
+    fn from(value: io::Error) -> Self {
+        FeedError(value.to_string())
+    }
+}
+
+/// The result of comparing the installed version with a feed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckResult {
+    /// The installed version, or `None` if SealDice is not installed.
+    pub installed: Option<Version>,
+    /// The newest release for this platform and channel, if any.
+    pub latest: Option<(Version, Release)>,
+}
+
+impl CheckResult {
+    /// Whether the newest release is newer than the installed version, or SealDice is not
+    /// installed at all.
+    pub fn update_available(&self) -> bool {
+        match (&self.installed, &self.latest) {
+            (_, None) => false,
+            (None, Some(_)) => true,
+            (Some(installed), Some((latest, _))) => latest > installed,
+        }
+    }
+}
+
+/// Compares the version of the executable in `root` with the newest release of the feed.
+pub fn check(feed: &Feed, channel: Channel, root: &Path, binary_name: &str) -> Result<CheckResult, FeedError> {
+    let installed = version::installed_version(root, binary_name)?;
+    let latest = feed.latest(channel).map(|(v, r)| (v, r.clone()));
+    Ok(CheckResult { installed, latest })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::{Channel, CheckResult, Feed};
+    use crate::version::Version;
+
+    fn feed() -> Feed {
+        let release = |version: &str, channel: &str, os: &str| {
+            format!(
+                r#"{{"version": "{}", "channel": "{}", "os": "{}", "arch": "{}", "url": "https://example.com/{}", "sha256": "00"}}"#,
+                version,
+                channel,
+                os,
+                env::consts::ARCH,
+                version
+            )
+        };
+        let other_os = if env::consts::OS == "windows" { "linux" } else { "windows" };
+        let text = format!(
+            r#"{{"releases": [{}, {}, {}, {}]}}"#,
+            release("1.4.6", "stable", env::consts::OS),
+            release("1.5.0", "stable", other_os),
+            release("1.5.0-dev.3", "dev", env::consts::OS),
+            release("1.4.5", "stable", env::consts::OS)
+        );
+        Feed::parse(&text).unwrap()
+    }
+
+    #[test]
+    fn latest_release_matches_platform_and_channel() {
+        let feed = feed();
+        let (version, release) = feed.latest(Channel::Stable).unwrap();
+        assert_eq!(version, Version::new(1, 4, 6));
+        assert_eq!(release.url, "https://example.com/1.4.6");
+
+        let (version, _) = feed.latest(Channel::Dev).unwrap();
+        assert_eq!(version.to_string(), "1.5.0-dev.3");
+    }
+
+    #[test]
+    fn update_is_available_for_newer_or_missing_install() {
+        let latest = feed().latest(Channel::Stable).map(|(v, r)| (v, r.clone()));
+        let result = |installed| CheckResult {
+            installed,
+            latest: latest.clone(),
+        };
+        assert!(result(Some(Version::new(1, 4, 5))).update_available());
+        assert!(!result(Some(Version::new(1, 4, 6))).update_available());
+        assert!(result(None).update_available());
+    }
+}
@@ -0,0 +1,161 @@
+//! Runs the hook commands configured for the stages of an update.
+
+use std::{
+    fmt, io,
+    process::{Command, Stdio},
+    str::FromStr,
+};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::{event::Event, i18n::Msg, log::Logger, proc, status::Status, updater::UpdateOptions};
+
+/// The points of an update at which hooks run.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HookStage {
+    /// Before stopping bundled processes, once the package has been verified.
+    PreStop,
+    /// After backing up the main executable.
+    PostBackup,
+    /// After extracting the package and removing obsolete entries. Failing rolls the update
+    /// back.
+    PostExtract,
+    /// Before launching SealDice, even if launching is skipped.
+    PreLaunch,
+}
+
+impl fmt::Display for HookStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.to_possible_value() {
+            Some(value) => value.get_name().fmt(f),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A command run by the platform shell in the install root at a stage of the update.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Hook {
+    pub stage: HookStage,
+    pub command: String,
+}
+
+impl Hook {
+    pub fn new<S: Into<String>>(stage: HookStage, command: S) -> Hook {
+        Hook {
+            stage,
+            command: command.into(),
+        }
+    }
+}
+
+/// Parses a hook in the form `<STAGE>=<COMMAND>`, such as `post-extract=./migrate.sh`.
+impl FromStr for Hook {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Hook, String> {
+        let (stage, command) = s
+            .split_once('=')
+            .ok_or_else(|| String::from("expected <STAGE>=<COMMAND>"))?;
+        let stage = HookStage::from_str(stage.trim(), true)?;
+        if command.trim().is_empty() {
+            return Err(String::from("command is empty"));
+        }
+        Ok(Hook::new(stage, command))
+    }
+}
+
+/// Runs every hook of `stage` in order, logging their output. Fails on the first hook that
+/// cannot be started, exits with a non-zero code or runs longer than `options.hook_timeout`,
+/// which is then killed. Upon success, return the count of hooks run.
+pub fn run_hooks(options: &UpdateOptions, stage: HookStage, status: &Status, logger: &Logger) -> io::Result<usize> {
+    let mut count = 0;
+    for hook in options.hooks.iter().filter(|h| h.stage == stage) {
+        logger.info(Msg::HookRunning(&stage, &hook.command));
+
+        let mut command = shell_command(&hook.command);
+        command
+            .current_dir(&options.root)
+            .env("SEALUPD_STAGE", stage.to_string())
+            .env("SEALUPD_PACKAGE", &options.package)
+            .env("SEALUPD_BINARY", &options.binary_name)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        if let Ok(root) = options.root.canonicalize() {
+            command.env("SEALUPD_ROOT", root);
+        }
+        if let Some(version) = &status.from_version {
+            command.env("SEALUPD_FROM_VERSION", version);
+        }
+        if let Some(version) = &status.to_version {
+            command.env("SEALUPD_TO_VERSION", version);
+        }
+
+        let Some(output) = proc::output_with_timeout(&mut command, options.hook_timeout)? else {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                Msg::HookTimeout(&hook.command, options.hook_timeout.as_secs()).to_string(),
+            ));
+        };
+        for line in String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .chain(String::from_utf8_lossy(&output.stderr).lines())
+        {
+            logger.info(Msg::HookOutput(line));
+        }
+
+        if !output.status.success() {
+            return Err(io::Error::other(
+                Msg::HookExited(&hook.command, &output.status).to_string(),
+            ));
+        }
+        logger.event(Event::HookFinished {
+            stage,
+            command: hook.command.clone(),
+        });
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+/// Passes the command to cmd.exe as is, since it does not parse arguments quoted the way
+/// programs built with MSVC do.
+#[cfg(windows)]
+fn shell_command(command: &str) -> Command {
+    use std::os::windows::process::CommandExt;
+
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").raw_arg(command);
+    shell
+}
+
+#[cfg(not(windows))]
+fn shell_command(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Hook, HookStage};
+
+    #[test]
+    fn hooks_are_parsed_from_stage_and_command() {
+        assert_eq!(
+            "post-extract=./migrate.sh --all".parse::<Hook>(),
+            Ok(Hook::new(HookStage::PostExtract, "./migrate.sh --all"))
+        );
+        assert_eq!(
+            "pre-launch=a=b".parse::<Hook>(),
+            Ok(Hook::new(HookStage::PreLaunch, "a=b"))
+        );
+        assert!("./migrate.sh".parse::<Hook>().is_err());
+        assert!("after-all=./migrate.sh".parse::<Hook>().is_err());
+        assert!("pre-stop= ".parse::<Hook>().is_err());
+    }
+}
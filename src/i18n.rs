@@ -0,0 +1,677 @@
+//! Defines the catalogue of messages shown on the console and written to the log file.
+
+use std::{env, fmt, sync::OnceLock};
+
+use clap::ValueEnum;
+
+use crate::decompress::{BackupInfo, Conflict, NodeKind};
+
+/// The languages messages can be displayed in.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lang {
+    #[value(name = "zh-CN", alias = "zh")]
+    ZhCn,
+    #[value(name = "en")]
+    En,
+}
+
+static LANG: OnceLock<Lang> = OnceLock::new();
+
+/// Returns the language of messages, as set by [`set_lang`] or else detected from the system locale.
+pub fn lang() -> Lang {
+    *LANG.get_or_init(|| lang_from_locale(sys_locale::get_locale().as_deref()))
+}
+
+/// Sets the language of messages. Returns false if the language has already been set or used.
+pub fn set_lang(lang: Lang) -> bool {
+    LANG.set(lang).is_ok()
+}
+
+/// Chinese is chosen for Chinese locales, as well as for unknown or neutral ones such as `C` and
+/// `POSIX`, which are common on servers. English is chosen for every other locale.
+fn lang_from_locale(locale: Option<&str>) -> Lang {
+    let Some(locale) = locale else {
+        return Lang::ZhCn;
+    };
+    let locale = locale.trim().to_ascii_lowercase();
+
+    if locale.is_empty() || locale == "c" || locale.starts_with("c.") || locale == "posix" || locale.starts_with("zh") {
+        Lang::ZhCn
+    } else {
+        Lang::En
+    }
+}
+
+/// A message of the catalogue. Its `Display` implementation renders it in the language of [`lang()`].
+// Messages about Milky are only used on Windows.
+#[cfg_attr(not(windows), allow(dead_code))]
+pub enum Msg<'a> {
+    ConsoleLogStarted,
+    FileLogDisabled,
+    FileLogCreated(&'a str),
+    FileLogStarted,
+    FileLogFailed(&'a dyn fmt::Display),
+    LogsPruned(usize),
+    LogsPruneFailed(&'a dyn fmt::Display),
+    StatusWriteFailed(&'a str, &'a dyn fmt::Display),
+
+    Downloading(&'a str),
+    DownloadAttemptFailed(usize, usize, &'a dyn fmt::Display),
+    Downloaded(u64),
+    DownloadRemoveFailed(&'a dyn fmt::Display),
+    DownloadFailed(&'a dyn fmt::Display),
+
+    CheckFailed(&'a dyn fmt::Display),
+    NoRelease,
+    UpdateAvailable(&'a str, &'a str),
+    UpToDate(&'a str),
+    NotInstalled(&'a str),
+    ReleasePackage(&'a str, &'a str),
+
+    WaitingProcess(u32),
+    ProcessStillRunning,
+    ProcessExited,
+    PidIsSelf,
+    ProcessGone(u32),
+    ProcessIsSelf,
+    ProcessFound(&'a str, usize, usize),
+
+    NoMilkyRunning,
+    MilkyStopped(usize),
+    MilkyFound(u32),
+    MilkyKillFailed(u32),
+    MilkyTimeout(&'a str),
+    StopMilkyFailed(&'a dyn fmt::Display),
+
+    ManifestFound(&'a str),
+    ManifestMissing,
+    InstalledVersion(&'a str),
+    InstalledVersionUnknown,
+    InstalledVersionFailed(&'a dyn fmt::Display),
+    BinaryFromManifest(&'a str),
+    EntryPatched(&'a str),
+    PatchesApplied(usize),
+    Verified(usize),
+    VerifyFailed(&'a dyn fmt::Display),
+
+    Recovering,
+    RecoveryCompleted(usize),
+    RolledBack(usize, usize),
+    RecoverFailed(&'a dyn fmt::Display),
+    NothingToRecover,
+    RollbackFailed(&'a dyn fmt::Display),
+    JournalFinishFailed(&'a dyn fmt::Display),
+
+    BackupDone(&'a str),
+    BackupSkipped,
+    BackupFailed(&'a dyn fmt::Display),
+    BackupsRemoved(usize),
+    BackupsRemoveFailed(&'a dyn fmt::Display),
+    NoBackups,
+    BackupListed(&'a BackupInfo),
+    ListBackupsFailed(&'a dyn fmt::Display),
+
+    PackageDigest(&'a str),
+    PackageDigestFailed(&'a dyn fmt::Display),
+    Decompressing(&'a str),
+    Decompressed(usize),
+    FilesChanged(usize, usize, usize),
+    EntryUnchanged(&'a str),
+    EntryProtected(&'a str),
+    EntryInUse(&'a str, &'a str, u32),
+    EntriesInUse(usize, &'a str),
+    EntryWrittenAside(&'a str, &'a str),
+    ConflictReplaced(&'a Conflict),
+    ConflictSkipped(&'a Conflict),
+    ConflictProtected(&'a Conflict),
+    EntriesProtected(usize, &'a str),
+    DecompressFailed(&'a dyn fmt::Display),
+
+    FileListMissing,
+    FileListWriteFailed(&'a dyn fmt::Display),
+    EntryRemoved(&'a str),
+    Pruned(usize),
+    PruneFailed(&'a dyn fmt::Display),
+
+    HookRunning(&'a dyn fmt::Display, &'a str),
+    HookOutput(&'a str),
+    HookFailed(&'a dyn fmt::Display, &'a dyn fmt::Display),
+
+    QuarantineRemoved,
+    QuarantineFailed(&'a dyn fmt::Display),
+    PermissionSet,
+    PermissionFailed(&'a dyn fmt::Display),
+    LaunchSkipped,
+    LaunchScheduled,
+    RestartFailed(&'a dyn fmt::Display),
+
+    // Errors, which are embedded in the messages above.
+    NoDownloadUrl,
+    UnexpectedStatus(&'a str, u16),
+    ChecksumMismatch(&'a str, &'a str),
+    FeedInvalid(&'a dyn fmt::Display),
+    VersionTimeout(&'a str),
+    VersionNotFound(&'a str),
+    HookExited(&'a str, &'a dyn fmt::Display),
+    HookTimeout(&'a str, u64),
+    ManifestInvalid(&'a dyn fmt::Display),
+    MinVersionInvalid(&'a str),
+    EntryNotInManifest(&'a str),
+    EntryDigestMismatch(&'a str),
+    EntryMissing(&'a str),
+    WrongPlatform(&'a str, &'a str),
+    VersionTooOld(&'a str, &'a str),
+    PatchBaseUnreadable(&'a str, &'a dyn fmt::Display),
+    PatchBaseUnknown(&'a str),
+    PatchResultMismatch(&'a str),
+    EntrySlip(&'a str),
+    NamesNotPortable(&'a str),
+    NameDuplicate(&'a str),
+    NameCaseCollision(&'a str, &'a str),
+    NameReserved(&'a str),
+    NameTrailingDotOrSpace(&'a str),
+    NameIllegalChar(&'a str, char),
+    EntryConflict(&'a Conflict),
+    EntriesConflict(&'a str),
+    PackageChanged,
+    WorkersStopped,
+    JournalInvalid,
+    BackupMissing(&'a str),
+}
+
+impl Msg<'_> {
+    fn fmt_zh_cn(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Msg::ConsoleLogStarted => write!(f, "终端日志开始记录"),
+            Msg::FileLogDisabled => write!(f, "日志文件已关闭"),
+            Msg::FileLogCreated(name) => write!(f, "已创建日志文件 '{}'", name),
+            Msg::FileLogStarted => write!(f, "文件日志开始记录"),
+            Msg::FileLogFailed(err) => write!(f, "无法创建文件日志: {}", err),
+            Msg::LogsPruned(count) => write!(f, "已清理 {} 个旧日志文件", count),
+            Msg::LogsPruneFailed(err) => write!(f, "清理旧日志文件出错: {}", err),
+            Msg::StatusWriteFailed(name, err) => write!(f, "无法写入状态文件 '{}': {}", name, err),
+
+            Msg::Downloading(url) => write!(f, "从 '{}' 下载更新包", url),
+            Msg::DownloadAttemptFailed(attempt, max, err) => {
+                write!(f, "下载失败, 尝试次数 {}/{}: {}", attempt, max, err)
+            }
+            Msg::Downloaded(bytes) => write!(f, "下载完成, 共 {} 字节, 校验通过", bytes),
+            Msg::DownloadRemoveFailed(err) => write!(f, "无法删除已下载的更新包: {}", err),
+            Msg::DownloadFailed(err) => write!(f, "无法下载更新包, 中止操作: {}", err),
+
+            Msg::CheckFailed(err) => write!(f, "检查更新失败: {}", err),
+            Msg::NoRelease => write!(f, "发布源中没有适用于当前平台和通道的版本"),
+            Msg::UpdateAvailable(installed, latest) => write!(f, "有可用更新: {} -> {}", installed, latest),
+            Msg::UpToDate(installed) => write!(f, "已是最新版本 {}", installed),
+            Msg::NotInstalled(latest) => write!(f, "未找到已安装的主程序, 最新版本为 {}", latest),
+            Msg::ReleasePackage(url, sha256) => write!(f, "更新包: {} (SHA-256: {})", url, sha256),
+
+            Msg::WaitingProcess(pid) => write!(f, "等待进程 {} 退出", pid),
+            Msg::ProcessStillRunning => write!(f, "经等待进程仍未退出, 为避免错误, 中止操作"),
+            Msg::ProcessExited => write!(f, "进程成功退出, 继续操作"),
+            Msg::PidIsSelf => write!(f, "当前进程 ID 等于要等待的 ID, 推断进程已经继承"),
+            Msg::ProcessGone(pid) => write!(f, "进程 {} 已不存在, 推断已经结束", pid),
+            Msg::ProcessIsSelf => write!(f, "进程名称等于升级器名称, 推断进程已经继承"),
+            Msg::ProcessFound(name, attempt, max) => write!(f, "找到进程 {}, 尝试次数 {}/{}", name, attempt, max),
+
+            Msg::NoMilkyRunning => write!(f, "未发现仍在运行的内置 Milky 进程"),
+            Msg::MilkyStopped(count) => write!(f, "已经结束 {} 个内置 Milky 进程", count),
+            Msg::MilkyFound(pid) => write!(f, "发现仍在运行的内置 Milky 进程 {}, 尝试结束", pid),
+            Msg::MilkyKillFailed(pid) => write!(f, "无法结束内置 Milky 进程 {}", pid),
+            Msg::MilkyTimeout(pids) => write!(f, "等待内置 Milky 进程退出超时: {}", pids),
+            Msg::StopMilkyFailed(err) => write!(f, "结束内置 Milky 进程失败, 为避免错误, 中止操作: {}", err),
+
+            Msg::ManifestFound(version) => write!(f, "更新包附带清单, 版本 {}", version),
+            Msg::ManifestMissing => write!(f, "更新包未附带清单, 跳过清单校验"),
+            Msg::InstalledVersion(version) => write!(f, "当前安装版本 {}", version),
+            Msg::InstalledVersionUnknown => write!(f, "未找到已安装的主程序, 跳过版本检查"),
+            Msg::InstalledVersionFailed(err) => write!(f, "无法获取已安装版本, 跳过版本检查: {}", err),
+            Msg::BinaryFromManifest(name) => write!(f, "按清单使用主程序名称 '{}'", name),
+            Msg::EntryPatched(name) => write!(f, "已将补丁应用于 '{}' 的暂存副本", name),
+            Msg::PatchesApplied(count) => write!(f, "已应用 {} 个增量补丁并通过校验", count),
+            Msg::Verified(count) => write!(f, "更新包校验通过, 共计 {} 条目", count),
+            Msg::VerifyFailed(err) => write!(f, "更新包校验失败, 未做任何改动: {}", err),
+
+            Msg::Recovering => write!(f, "发现上次未完成的更新, 正在恢复"),
+            Msg::RecoveryCompleted(count) => write!(f, "上次更新已写入全部 {} 个文件, 保留新版本", count),
+            Msg::RolledBack(removed, restored) => {
+                write!(
+                    f,
+                    "已经撤销未完成的更新: 删除 {} 个文件, 从备份恢复 {} 个文件",
+                    removed, restored
+                )
+            }
+            Msg::RecoverFailed(err) => write!(f, "恢复未完成的更新失败, 请从 backups/updater 手动恢复: {}", err),
+            Msg::NothingToRecover => write!(f, "没有未完成的更新"),
+            Msg::RollbackFailed(err) => write!(f, "撤销失败的更新出错, 下次运行时将再次尝试: {}", err),
+            Msg::JournalFinishFailed(err) => write!(f, "删除更新日志失败: {}", err),
+            Msg::BackupDone(path) => write!(f, "已经将可执行文件备份到 '{}'", path),
+            Msg::BackupSkipped => write!(f, "可执行文件不存在, 跳过备份"),
+            Msg::BackupFailed(err) => write!(f, "备份可执行文件失败, 为安全考虑, 中止操作: {}", err),
+            Msg::BackupsRemoved(count) => write!(f, "已经删除 {} 个旧备份", count),
+            Msg::BackupsRemoveFailed(err) => write!(f, "删除旧备份失败: {}", err),
+            Msg::NoBackups => write!(f, "没有找到备份"),
+            Msg::BackupListed(backup) => write!(
+                f,
+                "{}  {} -> {}  {} 个文件, {} 字节  ({})",
+                backup.name,
+                backup.from_version.as_deref().unwrap_or("?"),
+                backup.to_version.as_deref().unwrap_or("?"),
+                backup.files,
+                backup.bytes,
+                backup.package
+            ),
+            Msg::ListBackupsFailed(err) => write!(f, "读取备份失败: {}", err),
+
+            Msg::PackageDigest(digest) => write!(f, "更新包 SHA-256: {}", digest),
+            Msg::PackageDigestFailed(err) => write!(f, "无法计算更新包校验值: {}", err),
+            Msg::Decompressing(package) => write!(f, "尝试解压 '{}'", package),
+            Msg::Decompressed(count) => write!(f, "解压成功, 共计 {} 条目", count),
+            Msg::FilesChanged(created, updated, unchanged) => write!(
+                f,
+                "新建 {} 个文件, 更新 {} 个文件, {} 个文件内容未变, 未重写",
+                created, updated, unchanged
+            ),
+            Msg::EntryUnchanged(name) => write!(f, "'{}' 内容未变, 跳过", name),
+            Msg::EntryProtected(name) => write!(f, "'{}' 已存在且受保护, 跳过", name),
+            Msg::EntryInUse(name, process, pid) => {
+                write!(
+                    f,
+                    "'{}' 正在被进程 {} (PID {}) 运行, 该进程重启后才会使用新版本",
+                    name, process, pid
+                )
+            }
+            Msg::EntriesInUse(count, names) => {
+                write!(f, "替换了 {} 个仍在运行的文件, 请重启相应的组件: {}", count, names)
+            }
+            Msg::EntryWrittenAside(name, new_name) => write!(f, "'{}' 已存在且受保护, 写入 '{}'", name, new_name),
+            Msg::ConflictReplaced(conflict) => write!(
+                f,
+                "'{}' 是{}, 但更新包中是{}, 已将其移入备份并替换",
+                conflict.path,
+                node_zh_cn(conflict.existing),
+                node_zh_cn(conflict.entry())
+            ),
+            Msg::ConflictSkipped(conflict) => write!(
+                f,
+                "'{}' 是{}, 但更新包中是{}, 保留原有{}并跳过对应条目",
+                conflict.path,
+                node_zh_cn(conflict.existing),
+                node_zh_cn(conflict.entry()),
+                node_zh_cn(conflict.existing)
+            ),
+            Msg::ConflictProtected(conflict) => write!(
+                f,
+                "'{}' 是{}, 但更新包中是{}, 其中有受保护的文件, 保留原有{}并跳过对应条目",
+                conflict.path,
+                node_zh_cn(conflict.existing),
+                node_zh_cn(conflict.entry()),
+                node_zh_cn(conflict.existing)
+            ),
+            Msg::EntriesProtected(count, names) => write!(f, "共 {} 个受保护的文件未被覆盖: {}", count, names),
+            Msg::DecompressFailed(err) => write!(f, "解压失败: {}", err),
+
+            Msg::FileListMissing => write!(f, "未找到上次更新的文件记录, 仅按清单删除过时文件"),
+            Msg::FileListWriteFailed(err) => write!(f, "无法写入文件记录, 下次更新将无法清理过时文件: {}", err),
+            Msg::EntryRemoved(name) => write!(f, "已将过时文件 '{}' 移入备份目录", name),
+            Msg::Pruned(count) => write!(f, "已清理 {} 个过时文件", count),
+            Msg::PruneFailed(err) => write!(f, "清理过时文件失败, 可从备份目录恢复: {}", err),
+
+            Msg::HookRunning(stage, command) => write!(f, "运行 {} 钩子: {}", stage, command),
+            Msg::HookOutput(line) => write!(f, "[hook] {}", line),
+            Msg::HookFailed(stage, err) => write!(f, "{} 钩子运行失败, 中止操作: {}", stage, err),
+
+            Msg::QuarantineRemoved => write!(f, "成功除去可执行文件隔离属性"),
+            Msg::QuarantineFailed(err) => write!(f, "未能除去可执行文件隔离属性, 运行可能出错: {}", err),
+            Msg::PermissionSet => write!(f, "成功设置可执行文件权限"),
+            Msg::PermissionFailed(err) => write!(f, "设置可执行文件权限出错, 运行可能失败: {}", err),
+            Msg::LaunchSkipped => write!(f, "跳过重启主程序"),
+            Msg::LaunchScheduled => write!(f, "3 秒后尝试重启主程序. 跨进程指令出现的错误可能不会被记录"),
+            Msg::RestartFailed(err) => write!(f, "重启主程序出错: {}", err),
+
+            Msg::NoDownloadUrl => write!(f, "没有可供下载的地址"),
+            Msg::UnexpectedStatus(url, status) => write!(f, "{}: 意外的响应状态 {}", url, status),
+            Msg::ChecksumMismatch(expected, actual) => write!(f, "校验值不符, 应为 {}, 实为 {}", expected, actual),
+            Msg::FeedInvalid(err) => write!(f, "发布源格式无效: {}", err),
+            Msg::VersionTimeout(name) => write!(f, "'{} --version' 未能及时退出", name),
+            Msg::VersionNotFound(name) => write!(f, "'{} --version' 的输出中没有版本号", name),
+            Msg::HookExited(command, status) => write!(f, "钩子 '{}' 退出状态为 {}", command, status),
+            Msg::HookTimeout(command, secs) => write!(f, "钩子 '{}' 未在 {} 秒内退出, 已将其终止", command, secs),
+            Msg::ManifestInvalid(err) => write!(f, "清单无效: {}", err),
+            Msg::MinVersionInvalid(version) => write!(f, "最低前置版本 '{}' 无效", version),
+            Msg::EntryNotInManifest(name) => write!(f, "条目 '{}' 未在清单中列出", name),
+            Msg::EntryDigestMismatch(name) => write!(f, "条目 '{}' 的校验值与清单不符", name),
+            Msg::EntryMissing(name) => write!(f, "清单中列出的条目 '{}' 不存在", name),
+            Msg::WrongPlatform(os, arch) => write!(
+                f,
+                "更新包适用于 {}/{}, 但升级器运行于 {}/{}",
+                os,
+                arch,
+                env::consts::OS,
+                env::consts::ARCH
+            ),
+            Msg::VersionTooOld(installed, required) => {
+                write!(f, "已安装版本 {} 低于更新包要求的最低版本 {}", installed, required)
+            }
+            Msg::PatchBaseUnreadable(name, err) => write!(f, "无法读取要打补丁的 '{}': {}", name, err),
+            Msg::PatchBaseUnknown(name) => write!(f, "已安装的 '{}' 与补丁的所有基础版本均不符, 需要完整更新包", name),
+            Msg::PatchResultMismatch(name) => write!(f, "打补丁后的 '{}' 与清单中的校验值不符", name),
+            Msg::EntrySlip(name) => write!(f, "条目 '{}' 可能导致路径穿越", name.escape_debug()),
+            Msg::NamesNotPortable(issues) => write!(f, "条目名称无法在所有平台上使用: {}", issues),
+            Msg::NameDuplicate(name) => write!(f, "'{}' 出现了多次", name),
+            Msg::NameCaseCollision(a, b) => write!(f, "'{}' 与 '{}' 仅有大小写不同", a, b),
+            Msg::NameReserved(name) => write!(f, "'{}' 是 Windows 的保留名称", name),
+            Msg::NameTrailingDotOrSpace(name) => write!(f, "'{}' 以句点或空格结尾", name),
+            Msg::NameIllegalChar(name, c) => {
+                write!(f, "'{}' 含有 Windows 不允许的字符 '{}'", name, c.escape_debug())
+            }
+            Msg::EntryConflict(conflict) => write!(
+                f,
+                "'{}' 是{}, 但更新包中是{}",
+                conflict.path,
+                node_zh_cn(conflict.existing),
+                node_zh_cn(conflict.entry())
+            ),
+            Msg::EntriesConflict(conflicts) => write!(f, "条目与已安装的文件冲突: {}", conflicts),
+            Msg::PackageChanged => write!(f, "更新包在校验后被改动"),
+            Msg::WorkersStopped => write!(f, "解压线程意外停止"),
+            Msg::JournalInvalid => write!(f, "更新日志的开头不是一次更新"),
+            Msg::BackupMissing(name) => write!(f, "未完成更新的备份 '{}' 不存在", name),
+        }
+    }
+
+    fn fmt_en(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Msg::ConsoleLogStarted => write!(f, "Console logging started"),
+            Msg::FileLogDisabled => write!(f, "Log file disabled"),
+            Msg::FileLogCreated(name) => write!(f, "Created log file '{}'", name),
+            Msg::FileLogStarted => write!(f, "File logging started"),
+            Msg::FileLogFailed(err) => write!(f, "Unable to create log file: {}", err),
+            Msg::LogsPruned(count) => write!(f, "Removed {} old log file(s)", count),
+            Msg::LogsPruneFailed(err) => write!(f, "Failed to remove old log files: {}", err),
+            Msg::StatusWriteFailed(name, err) => write!(f, "Unable to write status file '{}': {}", name, err),
+
+            Msg::Downloading(url) => write!(f, "Downloading the package from '{}'", url),
+            Msg::DownloadAttemptFailed(attempt, max, err) => {
+                write!(f, "Download failed, attempt {}/{}: {}", attempt, max, err)
+            }
+            Msg::Downloaded(bytes) => write!(f, "Downloaded {} bytes, checksum verified", bytes),
+            Msg::DownloadRemoveFailed(err) => write!(f, "Failed to remove the downloaded package: {}", err),
+            Msg::DownloadFailed(err) => write!(f, "Unable to download the package, aborting: {}", err),
+
+            Msg::CheckFailed(err) => write!(f, "Failed to check for updates: {}", err),
+            Msg::NoRelease => write!(f, "The feed has no release for this platform and channel"),
+            Msg::UpdateAvailable(installed, latest) => write!(f, "Update available: {} -> {}", installed, latest),
+            Msg::UpToDate(installed) => write!(f, "Up to date at {}", installed),
+            Msg::NotInstalled(latest) => write!(f, "No installed executable found, the latest version is {}", latest),
+            Msg::ReleasePackage(url, sha256) => write!(f, "Package: {} (SHA-256: {})", url, sha256),
+
+            Msg::WaitingProcess(pid) => write!(f, "Waiting for process {} to exit", pid),
+            Msg::ProcessStillRunning => write!(f, "Process is still running after waiting, aborting to avoid errors"),
+            Msg::ProcessExited => write!(f, "Process exited, continuing"),
+            Msg::PidIsSelf => write!(f, "PID to wait for equals the current PID, assuming it was inherited"),
+            Msg::ProcessGone(pid) => write!(f, "Process {} no longer exists, assuming it has exited", pid),
+            Msg::ProcessIsSelf => write!(f, "Process is the updater itself, assuming it was inherited"),
+            Msg::ProcessFound(name, attempt, max) => write!(f, "Found process {}, attempt {}/{}", name, attempt, max),
+
+            Msg::NoMilkyRunning => write!(f, "No bundled Milky process is running"),
+            Msg::MilkyStopped(count) => write!(f, "Stopped {} bundled Milky process(es)", count),
+            Msg::MilkyFound(pid) => write!(f, "Bundled Milky process {} is still running, stopping it", pid),
+            Msg::MilkyKillFailed(pid) => write!(f, "Unable to stop bundled Milky process {}", pid),
+            Msg::MilkyTimeout(pids) => write!(f, "Timed out waiting for bundled Milky processes to exit: {}", pids),
+            Msg::StopMilkyFailed(err) => {
+                write!(
+                    f,
+                    "Failed to stop bundled Milky processes, aborting to avoid errors: {}",
+                    err
+                )
+            }
+
+            Msg::ManifestFound(version) => write!(f, "Package has a manifest, version {}", version),
+            Msg::ManifestMissing => write!(f, "Package has no manifest, skipping manifest checks"),
+            Msg::InstalledVersion(version) => write!(f, "Installed version is {}", version),
+            Msg::InstalledVersionUnknown => write!(f, "Installed executable not found, skipping version check"),
+            Msg::InstalledVersionFailed(err) => {
+                write!(
+                    f,
+                    "Unable to query the installed version, skipping version check: {}",
+                    err
+                )
+            }
+            Msg::BinaryFromManifest(name) => write!(f, "Using executable name '{}' from the manifest", name),
+            Msg::EntryPatched(name) => write!(f, "Patched a staged copy of '{}'", name),
+            Msg::PatchesApplied(count) => write!(f, "Applied and verified {} delta patch(es)", count),
+            Msg::Verified(count) => write!(f, "Verified the package, {} entries", count),
+            Msg::VerifyFailed(err) => write!(f, "Package verification failed, nothing was changed: {}", err),
+
+            Msg::Recovering => write!(f, "Found an update left unfinished, recovering"),
+            Msg::RecoveryCompleted(count) => write!(
+                f,
+                "The unfinished update had written all {} files, keeping the new release",
+                count
+            ),
+            Msg::RolledBack(removed, restored) => write!(
+                f,
+                "Rolled back the unfinished update: removed {} files and restored {} from the backup",
+                removed, restored
+            ),
+            Msg::RecoverFailed(err) => write!(
+                f,
+                "Failed to recover the unfinished update, restore it from backups/updater: {}",
+                err
+            ),
+            Msg::NothingToRecover => write!(f, "No unfinished update found"),
+            Msg::RollbackFailed(err) => write!(
+                f,
+                "Failed to roll back the failed update, it will be retried on the next run: {}",
+                err
+            ),
+            Msg::JournalFinishFailed(err) => write!(f, "Failed to remove the update journal: {}", err),
+            Msg::BackupDone(path) => write!(f, "Backed up the executable into '{}'", path),
+            Msg::BackupSkipped => write!(f, "Executable does not exist, skipping backup"),
+            Msg::BackupFailed(err) => write!(f, "Failed to back up the executable, aborting for safety: {}", err),
+            Msg::BackupsRemoved(count) => write!(f, "Removed {} old backups", count),
+            Msg::BackupsRemoveFailed(err) => write!(f, "Failed to remove old backups: {}", err),
+            Msg::NoBackups => write!(f, "No backups found"),
+            Msg::BackupListed(backup) => write!(
+                f,
+                "{}  {} -> {}  {} files, {} bytes  ({})",
+                backup.name,
+                backup.from_version.as_deref().unwrap_or("?"),
+                backup.to_version.as_deref().unwrap_or("?"),
+                backup.files,
+                backup.bytes,
+                backup.package
+            ),
+            Msg::ListBackupsFailed(err) => write!(f, "Failed to read backups: {}", err),
+
+            Msg::PackageDigest(digest) => write!(f, "Package SHA-256: {}", digest),
+            Msg::PackageDigestFailed(err) => write!(f, "Unable to compute the package checksum: {}", err),
+            Msg::Decompressing(package) => write!(f, "Decompressing '{}'", package),
+            Msg::Decompressed(count) => write!(f, "Decompressed {} entries", count),
+            Msg::FilesChanged(created, updated, unchanged) => write!(
+                f,
+                "{} file(s) created, {} updated, {} unchanged and not rewritten",
+                created, updated, unchanged
+            ),
+            Msg::EntryUnchanged(name) => write!(f, "'{}' is unchanged, skipping", name),
+            Msg::EntryProtected(name) => write!(f, "'{}' exists and is protected, skipping", name),
+            Msg::EntryInUse(name, process, pid) => write!(
+                f,
+                "'{}' is being executed by {} (PID {}), which runs the old version until restarted",
+                name, process, pid
+            ),
+            Msg::EntriesInUse(count, names) => write!(
+                f,
+                "Replaced {} files that were still running, restart those components: {}",
+                count, names
+            ),
+            Msg::EntryWrittenAside(name, new_name) => {
+                write!(f, "'{}' exists and is protected, writing '{}' instead", name, new_name)
+            }
+            Msg::ConflictReplaced(conflict) => {
+                write!(f, "{}; moved it into the backup and replaced it", conflict)
+            }
+            Msg::ConflictSkipped(conflict) => write!(f, "{}; keeping it and skipping the entries there", conflict),
+            Msg::ConflictProtected(conflict) => {
+                write!(
+                    f,
+                    "{}; it holds protected entries, keeping it and skipping the entries there",
+                    conflict
+                )
+            }
+            Msg::EntriesProtected(count, names) => write!(f, "{} protected file(s) not overwritten: {}", count, names),
+            Msg::DecompressFailed(err) => write!(f, "Decompression failed: {}", err),
+
+            Msg::FileListMissing => write!(
+                f,
+                "No files were recorded by the previous update, removing only those listed in the manifest"
+            ),
+            Msg::FileListWriteFailed(err) => write!(
+                f,
+                "Unable to record the files written, the next update cannot prune obsolete files: {}",
+                err
+            ),
+            Msg::EntryRemoved(name) => write!(f, "Moved obsolete '{}' into the backup directory", name),
+            Msg::Pruned(count) => write!(f, "Removed {} obsolete entries", count),
+            Msg::PruneFailed(err) => write!(
+                f,
+                "Failed to remove obsolete entries, they can be restored from the backup directory: {}",
+                err
+            ),
+
+            Msg::HookRunning(stage, command) => write!(f, "Running {} hook: {}", stage, command),
+            Msg::HookOutput(line) => write!(f, "[hook] {}", line),
+            Msg::HookFailed(stage, err) => write!(f, "{} hook failed, aborting: {}", stage, err),
+
+            Msg::QuarantineRemoved => write!(f, "Removed the quarantine attribute of the executable"),
+            Msg::QuarantineFailed(err) => {
+                write!(
+                    f,
+                    "Unable to remove the quarantine attribute, launching may fail: {}",
+                    err
+                )
+            }
+            Msg::PermissionSet => write!(f, "Set permissions of the executable"),
+            Msg::PermissionFailed(err) => write!(
+                f,
+                "Unable to set permissions of the executable, launching may fail: {}",
+                err
+            ),
+            Msg::LaunchSkipped => write!(f, "Skipping launch of the main program"),
+            Msg::LaunchScheduled => write!(
+                f,
+                "Launching the main program in 3 seconds. Errors from the new process might not be logged"
+            ),
+            Msg::RestartFailed(err) => write!(f, "Failed to launch the main program: {}", err),
+
+            Msg::NoDownloadUrl => write!(f, "no URL to download from"),
+            Msg::UnexpectedStatus(url, status) => write!(f, "{}: unexpected status {}", url, status),
+            Msg::ChecksumMismatch(expected, actual) => {
+                write!(f, "checksum mismatch, expected {} but got {}", expected, actual)
+            }
+            Msg::FeedInvalid(err) => write!(f, "invalid release feed: {}", err),
+            Msg::VersionTimeout(name) => write!(f, "'{} --version' did not exit in time", name),
+            Msg::VersionNotFound(name) => write!(f, "no version found in the output of '{} --version'", name),
+            Msg::HookExited(command, status) => write!(f, "hook '{}' exited with {}", command, status),
+            Msg::HookTimeout(command, secs) => {
+                write!(
+                    f,
+                    "hook '{}' did not exit within {} seconds and was killed",
+                    command, secs
+                )
+            }
+            Msg::ManifestInvalid(err) => write!(f, "invalid manifest: {}", err),
+            Msg::MinVersionInvalid(version) => write!(f, "invalid minimum previous version '{}'", version),
+            Msg::EntryNotInManifest(name) => write!(f, "entry '{}' is not listed in the manifest", name),
+            Msg::EntryDigestMismatch(name) => write!(f, "digest of entry '{}' does not match the manifest", name),
+            Msg::EntryMissing(name) => write!(f, "entry '{}' listed in the manifest is missing", name),
+            Msg::WrongPlatform(os, arch) => write!(
+                f,
+                "package is built for {}/{}, but this updater runs on {}/{}",
+                os,
+                arch,
+                env::consts::OS,
+                env::consts::ARCH
+            ),
+            Msg::VersionTooOld(installed, required) => write!(
+                f,
+                "installed version {} is older than {}, the oldest the package applies to",
+                installed, required
+            ),
+            Msg::PatchBaseUnreadable(name, err) => write!(f, "unable to read '{}' to patch: {}", name, err),
+            Msg::PatchBaseUnknown(name) => write!(
+                f,
+                "installed '{}' matches no base of its patch, the full package is required",
+                name
+            ),
+            Msg::PatchResultMismatch(name) => {
+                write!(f, "patched '{}' does not match the digest in the manifest", name)
+            }
+            Msg::EntrySlip(name) => write!(f, "entry '{}' might lead to slip exploit", name.escape_debug()),
+            Msg::NamesNotPortable(issues) => write!(f, "entry names are not portable: {}", issues),
+            Msg::NameDuplicate(name) => write!(f, "'{}' appears more than once", name),
+            Msg::NameCaseCollision(a, b) => write!(f, "'{}' and '{}' differ only in case", a, b),
+            Msg::NameReserved(name) => write!(f, "'{}' is a reserved name on Windows", name),
+            Msg::NameTrailingDotOrSpace(name) => write!(f, "'{}' ends with a dot or a space", name),
+            Msg::NameIllegalChar(name, c) => write!(
+                f,
+                "'{}' contains '{}', which Windows does not allow",
+                name,
+                c.escape_debug()
+            ),
+            Msg::EntryConflict(conflict) => match conflict.existing {
+                NodeKind::File => write!(f, "'{}' is a file, but a directory in the package", conflict.path),
+                NodeKind::Dir => write!(f, "'{}' is a directory, but a file in the package", conflict.path),
+            },
+            Msg::EntriesConflict(conflicts) => write!(f, "entries conflict with the install: {}", conflicts),
+            Msg::PackageChanged => write!(f, "package changed since it was inspected"),
+            Msg::WorkersStopped => write!(f, "extraction workers stopped unexpectedly"),
+            Msg::JournalInvalid => write!(f, "journal does not begin an update"),
+            Msg::BackupMissing(name) => write!(f, "backup '{}' of the unfinished update is missing", name),
+        }
+    }
+}
+
+impl fmt::Display for Msg<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match lang() {
+            Lang::ZhCn => self.fmt_zh_cn(f),
+            Lang::En => self.fmt_en(f),
+        }
+    }
+}
+
+fn node_zh_cn(kind: NodeKind) -> &'static str {
+    match kind {
+        NodeKind::File => "文件",
+        NodeKind::Dir => "目录",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Lang, lang_from_locale};
+
+    #[test]
+    fn chinese_and_neutral_locales_use_chinese() {
+        for locale in [
+            None,
+            Some(""),
+            Some("C"),
+            Some("C.UTF-8"),
+            Some("POSIX"),
+            Some("zh-CN"),
+            Some("zh_TW.UTF-8"),
+        ] {
+            assert_eq!(lang_from_locale(locale), Lang::ZhCn, "{:?}", locale);
+        }
+    }
+
+    #[test]
+    fn other_locales_use_english() {
+        for locale in ["en-US", "en_GB.UTF-8", "ja-JP", "de"] {
+            assert_eq!(lang_from_locale(Some(locale)), Lang::En, "{:?}", locale);
+        }
+    }
+}
@@ -0,0 +1,20 @@
+//! Updater for SealDice. The [`Updater`] waits for SealDice to exit, backs up its executable,
+//! decompresses an update package into its install root and launches it again.
+//!
+//! The same functionality is available from the command line through the `sealupd` binary.
+
+pub mod consts;
+pub mod decompress;
+pub mod download;
+pub mod event;
+pub mod exit_code;
+pub mod feed;
+pub mod hook;
+pub mod i18n;
+pub mod log;
+pub mod proc;
+pub mod status;
+pub mod updater;
+pub mod version;
+
+pub use updater::{UpdateOptions, Updater};
@@ -0,0 +1,235 @@
+//! Defines the [`Logger`] through which every message and event of an update is reported, and
+//! the [`Sink`] trait implemented by its destinations.
+
+use std::cmp::Reverse;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use clap::ValueEnum;
+use log::LevelFilter;
+
+use crate::event::Event;
+
+pub use sinks::{ConsoleSink, FileSink, JsonSink, MemorySink, Record};
+
+mod sinks;
+
+/// Prefix of the names of log files.
+const LOG_PREFIX: &str = "updater_";
+
+/// Suffix of the names of log files.
+const LOG_SUFFIX: &str = ".txt";
+
+/// Defines how progress and results are reported on the console.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human-readable lines.
+    Text,
+    /// One JSON object per line on stdout, for consumption by SealDice.
+    Json,
+}
+
+/// The severity of a human-readable message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Level {
+    Error,
+    Warn,
+    /// An informational message telling that something succeeded.
+    Success,
+    Info,
+    /// A message only shown when asked for more information.
+    Verbose,
+}
+
+impl Level {
+    /// Returns the least verbose filter that lets messages of this level through.
+    pub fn filter(self) -> LevelFilter {
+        match self {
+            Level::Error => LevelFilter::Error,
+            Level::Warn => LevelFilter::Warn,
+            Level::Success | Level::Info => LevelFilter::Info,
+            Level::Verbose => LevelFilter::Debug,
+        }
+    }
+}
+
+/// A destination of messages and events. Each sink decides for itself which of them to keep.
+pub trait Sink: Send + Sync {
+    /// Receives a human-readable message.
+    fn message(&self, level: Level, message: &str);
+
+    /// Receives a structured event. Ignored by default.
+    fn event(&self, event: &Event) {
+        _ = event;
+    }
+}
+
+impl<S: Sink + ?Sized> Sink for Arc<S> {
+    fn message(&self, level: Level, message: &str) {
+        (**self).message(level, message);
+    }
+
+    fn event(&self, event: &Event) {
+        (**self).event(event);
+    }
+}
+
+/// Forwards every message and event to each of its sinks.
+#[derive(Default)]
+pub struct Logger {
+    sinks: Vec<Box<dyn Sink>>,
+    warnings: Mutex<Vec<String>>,
+}
+
+impl Logger {
+    /// Creates a logger without any sink.
+    pub fn new() -> Logger {
+        Logger::default()
+    }
+
+    /// Adds a sink, see [`Logger::add`].
+    pub fn with<S: Sink + 'static>(mut self, sink: S) -> Logger {
+        self.add(sink);
+        self
+    }
+
+    /// Adds a sink, which receives every message and event reported from now on.
+    pub fn add<S: Sink + 'static>(&mut self, sink: S) {
+        self.sinks.push(Box::new(sink));
+    }
+
+    /// Returns every message reported through [`Logger::warn`] so far.
+    pub fn warnings(&self) -> Vec<String> {
+        self.warnings.lock().map(|w| w.clone()).unwrap_or_default()
+    }
+
+    pub fn error<D: fmt::Display>(&self, message: D) {
+        self.message(Level::Error, message);
+    }
+
+    /// Also records the message, see [`Logger::warnings`].
+    pub fn warn<D: fmt::Display>(&self, message: D) {
+        let message = message.to_string();
+        if let Ok(mut warnings) = self.warnings.lock() {
+            warnings.push(message.clone());
+        }
+        self.message(Level::Warn, message);
+    }
+
+    pub fn success<D: fmt::Display>(&self, message: D) {
+        self.message(Level::Success, message);
+    }
+
+    pub fn info<D: fmt::Display>(&self, message: D) {
+        self.message(Level::Info, message);
+    }
+
+    pub fn verbose<D: fmt::Display>(&self, message: D) {
+        self.message(Level::Verbose, message);
+    }
+
+    pub fn message<D: fmt::Display>(&self, level: Level, message: D) {
+        if self.sinks.is_empty() {
+            return;
+        }
+        let message = message.to_string();
+        for sink in &self.sinks {
+            sink.message(level, &message);
+        }
+    }
+
+    pub fn event(&self, event: Event) {
+        for sink in &self.sinks {
+            sink.event(&event);
+        }
+    }
+}
+
+/// Returns the path of a new log file in `dir`, named after the current time.
+pub fn log_path(dir: &Path) -> PathBuf {
+    let date = chrono::Local::now().format("%y%m%d_%H%M%S");
+    dir.join(format!("{}{}{}", LOG_PREFIX, date, LOG_SUFFIX))
+}
+
+/// Removes log files in `dir` other than `current`, keeping at most `keep` of the newest ones and
+/// none older than `max_age`. Either limit is disabled if zero. Upon success, the count of files
+/// removed is returned.
+pub fn prune_logs(dir: &Path, current: &Path, keep: usize, max_age: Duration) -> io::Result<usize> {
+    let mut logs = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !(name.starts_with(LOG_PREFIX) && name.ends_with(LOG_SUFFIX)) || entry.path() == current {
+            continue;
+        }
+        if entry.file_type()?.is_file() {
+            logs.push((entry.path(), entry.metadata()?.modified()?));
+        }
+    }
+
+    let stale = stale_logs(logs, keep, max_age, SystemTime::now());
+    for path in &stale {
+        fs::remove_file(path)?;
+    }
+
+    Ok(stale.len())
+}
+
+/// Selects the logs to remove. As the current log is excluded from `logs`, one fewer than `keep`
+/// of them are kept.
+fn stale_logs(mut logs: Vec<(PathBuf, SystemTime)>, keep: usize, max_age: Duration, now: SystemTime) -> Vec<PathBuf> {
+    logs.sort_by_key(|(_, modified)| Reverse(*modified));
+
+    logs.into_iter()
+        .enumerate()
+        .filter(|(index, (_, modified))| {
+            let too_many = keep != 0 && index + 1 >= keep;
+            let too_old = !max_age.is_zero() && now.duration_since(*modified).is_ok_and(|age| age > max_age);
+            too_many || too_old
+        })
+        .map(|(_, (path, _))| path)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::PathBuf,
+        time::{Duration, SystemTime},
+    };
+
+    use super::stale_logs;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    fn logs(now: SystemTime, ages_in_days: &[u64]) -> Vec<(PathBuf, SystemTime)> {
+        ages_in_days
+            .iter()
+            .map(|days| (PathBuf::from(format!("{}", days)), now - DAY * (*days as u32)))
+            .collect()
+    }
+
+    #[test]
+    fn oldest_logs_beyond_count_are_removed() {
+        let now = SystemTime::now();
+        let stale = stale_logs(logs(now, &[3, 1, 2, 5]), 3, Duration::ZERO, now);
+        assert_eq!(stale, vec![PathBuf::from("3"), PathBuf::from("5")]);
+    }
+
+    #[test]
+    fn logs_beyond_age_are_removed() {
+        let now = SystemTime::now();
+        let stale = stale_logs(logs(now, &[40, 1, 31]), 0, DAY * 30, now);
+        assert_eq!(stale, vec![PathBuf::from("31"), PathBuf::from("40")]);
+    }
+
+    #[test]
+    fn zero_limits_keep_everything() {
+        let now = SystemTime::now();
+        assert!(stale_logs(logs(now, &[400, 1, 31]), 0, Duration::ZERO, now).is_empty());
+    }
+}
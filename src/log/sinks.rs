@@ -0,0 +1,151 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    sync::Mutex,
+};
+
+use chrono::Local;
+use log::LevelFilter;
+
+use super::{Level, Sink};
+use crate::{consts::IS_ASCII_SUPPORTED, event::Event};
+
+/// Writes human-readable messages up to a level to the console, ignoring events.
+pub struct ConsoleSink {
+    level: LevelFilter,
+}
+
+impl ConsoleSink {
+    pub fn new(level: LevelFilter) -> ConsoleSink {
+        ConsoleSink { level }
+    }
+}
+
+impl Sink for ConsoleSink {
+    fn message(&self, level: Level, message: &str) {
+        if level.filter() > self.level {
+            return;
+        }
+
+        let (header, colour) = match level {
+            Level::Error => ("E", "\x1b[1;31m"),
+            Level::Warn => ("W", "\x1b[1;33m"),
+            Level::Success => ("O", "\x1b[1;32m"),
+            Level::Info | Level::Verbose => ("", ""),
+        };
+        let line = if header.is_empty() {
+            format!("{}\n", message)
+        } else if *IS_ASCII_SUPPORTED {
+            format!("{}{}\x1b[0m {}\n", colour, header, message)
+        } else {
+            format!("{} {}\n", header, message)
+        };
+
+        _ = match level {
+            Level::Error | Level::Warn | Level::Info => io::stderr().write_all(line.as_bytes()),
+            Level::Success | Level::Verbose => io::stdout().write_all(line.as_bytes()),
+        };
+    }
+}
+
+/// Appends timestamped messages up to a level to a log file, ignoring events.
+pub struct FileSink {
+    file: Mutex<File>,
+    level: LevelFilter,
+}
+
+impl FileSink {
+    pub fn create(path: &Path, level: LevelFilter) -> io::Result<FileSink> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(FileSink {
+            file: Mutex::new(file),
+            level,
+        })
+    }
+}
+
+impl Sink for FileSink {
+    fn message(&self, level: Level, message: &str) {
+        if level.filter() > self.level {
+            return;
+        }
+
+        let line = format!(
+            "{} [{}] {}\n",
+            Local::now().format("%F %H:%M:%S%.3f"),
+            level.filter(),
+            message
+        );
+        if let Ok(mut file) = self.file.lock() {
+            _ = file.write_all(line.as_bytes());
+        }
+    }
+}
+
+/// Writes each event as a line of JSON to stdout, ignoring messages, so that stdout carries
+/// nothing but events.
+pub struct JsonSink;
+
+impl Sink for JsonSink {
+    fn message(&self, _level: Level, _message: &str) {}
+
+    fn event(&self, event: &Event) {
+        let mut stdout = io::stdout().lock();
+        if serde_json::to_writer(&mut stdout, event).is_ok() {
+            _ = writeln!(stdout);
+            _ = stdout.flush();
+        }
+    }
+}
+
+/// Something reported to a [`MemorySink`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Record {
+    Message(Level, String),
+    Event(Event),
+}
+
+/// Keeps every message and event in memory, so that they can be inspected afterwards.
+#[derive(Default)]
+pub struct MemorySink {
+    records: Mutex<Vec<Record>>,
+}
+
+impl MemorySink {
+    pub fn new() -> MemorySink {
+        MemorySink::default()
+    }
+
+    /// Returns every message and event received so far, in order.
+    pub fn records(&self) -> Vec<Record> {
+        self.records.lock().map(|r| r.clone()).unwrap_or_default()
+    }
+
+    /// Returns every event received so far, in order.
+    pub fn events(&self) -> Vec<Event> {
+        self.records()
+            .into_iter()
+            .filter_map(|record| match record {
+                Record::Event(event) => Some(event),
+                Record::Message(..) => None,
+            })
+            .collect()
+    }
+
+    fn push(&self, record: Record) {
+        if let Ok(mut records) = self.records.lock() {
+            records.push(record);
+        }
+    }
+}
+
+impl Sink for MemorySink {
+    fn message(&self, level: Level, message: &str) {
+        self.push(Record::Message(level, message.to_owned()));
+    }
+
+    fn event(&self, event: &Event) {
+        self.push(Record::Event(event.clone()));
+    }
+}
@@ -1,15 +1,35 @@
-use consts::CLI_ARGS;
-use log::Logger;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use clap::Parser;
+use sealupd::{
+    UpdateOptions, Updater,
+    consts::DOWNLOAD_DIR,
+    decompress::{self, Recovery},
+    download,
+    event::Event,
+    exit_code::ExitCode,
+    feed::{self, Channel, Feed},
+    i18n::{self, Msg},
+    log::{self, ConsoleSink, FileSink, JsonSink, Logger, OutputFormat},
+};
+
+use cli::{CliArgs, Command};
 
 mod cli;
-mod consts;
-mod decompress;
-mod log;
-mod proc;
 
 fn main() {
-    let exit_code = run();
-    if exit_code != 0 && cfg!(windows) {
+    let args = CliArgs::parse();
+    let exit_code = match &args.command {
+        Some(Command::Check { feed, channel, timeout }) => check(&args, feed, *channel, *timeout),
+        Some(Command::Recover) => recover(&args),
+        Some(Command::ListBackups) => list_backups(&args),
+        None => run(&args),
+    };
+    if exit_code != 0 && args.command.is_none() && cfg!(windows) && args.output == OutputFormat::Text {
         use std::io::{self, Read};
         println!("\nPress ENTER to continue ...");
         _ = io::stdin().read_exact(&mut [0u8]);
@@ -17,72 +37,164 @@ fn main() {
     std::process::exit(exit_code);
 }
 
-fn run() -> i32 {
-    let logger = Logger::new();
-    init_logger(&logger);
-    logger.console_success("终端日志开始记录");
+/// Sets the language and creates a logger writing to the console in the format of `--output`.
+fn console_logger(args: &CliArgs) -> Logger {
+    if let Some(lang) = args.lang {
+        i18n::set_lang(lang);
+    }
 
-    if CLI_ARGS.pid != 0 {
-        logger.batch_verbose(format_args!("等待进程 {} 退出", CLI_ARGS.pid));
-        if !proc::wait_process(CLI_ARGS.pid, 30, &logger) {
-            logger.batch_error("经等待进程仍未退出, 为避免错误, 中止操作");
-            return 1;
-        }
-        logger.batch_success("进程成功退出, 继续操作");
+    let mut logger = Logger::new();
+    match args.output {
+        OutputFormat::Text => logger.add(ConsoleSink::new(args.log_level())),
+        OutputFormat::Json => logger.add(JsonSink),
     }
+    logger
+}
+
+fn run(args: &CliArgs) -> i32 {
+    let mut logger = console_logger(args);
+    logger.success(Msg::ConsoleLogStarted);
+    let log_file = init_file_log(args, &mut logger);
+
+    let package = match (&args.package, &args.url) {
+        (Some(package), _) => PathBuf::from(package),
+        (None, Some(url)) => Path::new(DOWNLOAD_DIR).join(download::file_name(url)),
+        (None, None) => unreachable!("clap requires either --package or --url"),
+    };
+    let mut options = UpdateOptions::new(package)
+        .download(args.download())
+        .binary_name(&args.binary_name)
+        .pid(args.pid.filter(|&pid| pid != 0))
+        .skip_launch(args.skip_launch)
+        .prune(args.prune)
+        .protect_mode(args.protect_mode)
+        .conflict_policy(args.on_conflict)
+        .dir_sync(args.dir_sync)
+        .jobs(args.jobs)
+        .zip_encoding(args.zip_encoding)
+        .keep_backups(args.keep_backups)
+        .hook_timeout(Duration::from_secs(args.hook_timeout))
+        .log_file(log_file);
+    options.protect.extend(args.protect.iter().cloned());
+    options.hooks.extend(args.hook.iter().cloned());
 
-    #[cfg(windows)]
-    match proc::stop_local_yogurt(&logger) {
-        Ok(0) => logger.batch_verbose("未发现仍在运行的内置 Milky 进程"),
-        Ok(count) => logger.batch_success(format_args!("已经结束 {} 个内置 Milky 进程", count)),
+    Updater::new(options, &logger).run().exit_code.code()
+}
+
+/// Compares the installed version with the newest release of the feed.
+fn check(args: &CliArgs, feed: &str, channel: Channel, timeout: u64) -> i32 {
+    let logger = console_logger(args);
+    let result = Feed::load(feed, Duration::from_secs(timeout))
+        .and_then(|feed| feed::check(&feed, channel, Path::new("."), &args.binary_name));
+    let result = match result {
+        Ok(result) => result,
         Err(err) => {
-            logger.batch_error(format_args!("结束内置 Milky 进程失败, 为避免错误, 中止操作: {}", err));
-            return 1;
+            logger.error(Msg::CheckFailed(&err));
+            return ExitCode::CheckFailed.code();
         }
-    }
+    };
 
-    match decompress::backup_sealdice() {
-        Ok(exists) => {
-            if exists {
-                logger.batch_success("已经备份可执行文件");
-            } else {
-                logger.batch_info("可执行文件不存在, 跳过备份");
-            }
+    let installed = result.installed.as_ref().map(|v| v.to_string());
+    let latest = result.latest.as_ref().map(|(v, _)| v.to_string());
+    match (&installed, &latest) {
+        (_, None) => logger.info(Msg::NoRelease),
+        (None, Some(latest)) => logger.info(Msg::NotInstalled(latest)),
+        (Some(installed), Some(latest)) if result.update_available() => {
+            logger.success(Msg::UpdateAvailable(installed, latest))
         }
+        (Some(installed), Some(_)) => logger.info(Msg::UpToDate(installed)),
+    }
+    if let Some((_, release)) = &result.latest
+        && result.update_available()
+    {
+        logger.info(Msg::ReleasePackage(&release.url, &release.sha256));
+    }
+
+    let update_available = result.update_available();
+    logger.event(Event::Checked {
+        installed,
+        latest,
+        update_available,
+        release: result.latest.map(|(_, release)| release),
+    });
+    if update_available {
+        ExitCode::UpdateAvailable.code()
+    } else {
+        ExitCode::Success.code()
+    }
+}
+
+/// Completes or rolls back an update left unfinished in the current directory.
+fn recover(args: &CliArgs) -> i32 {
+    let logger = console_logger(args);
+    let root = Path::new(".");
+    if !decompress::is_unfinished(root) {
+        logger.info(Msg::NothingToRecover);
+        logger.event(Event::Recovered {
+            recovery: Recovery::Clean,
+        });
+        return ExitCode::Success.code();
+    }
+
+    logger.warn(Msg::Recovering);
+    match decompress::recover(root, &logger) {
+        Ok(_) => ExitCode::Success.code(),
         Err(err) => {
-            logger.batch_error(format_args!("备份可执行文件失败, 为安全考虑, 中止操作: {}", err));
-            return 1;
+            logger.error(Msg::RecoverFailed(&err));
+            ExitCode::RecoverFailed.code()
         }
     }
+}
 
-    logger.batch_info(format_args!("尝试解压 '{}'", CLI_ARGS.package));
-    match decompress::decompress(&logger) {
-        Ok(entry_count) => logger.batch_success(format_args!("解压成功, 共计 {} 条目", entry_count)),
+/// Lists the backups of the current directory.
+fn list_backups(args: &CliArgs) -> i32 {
+    let logger = console_logger(args);
+    let backups = match decompress::list_backups(Path::new(".")) {
+        Ok(backups) => backups,
         Err(err) => {
-            logger.batch_error(format_args!("解压失败: {}", err));
-            return 1;
+            logger.error(Msg::ListBackupsFailed(&err));
+            return ExitCode::BackupFailed.code();
         }
-    }
+    };
 
-    if let Err(err) = proc::restart_sealdice(&logger) {
-        logger.batch_error(format_args!("重启主程序出错: {}", err));
-        return 1;
+    if backups.is_empty() {
+        logger.info(Msg::NoBackups);
     }
-
-    0
+    for backup in &backups {
+        logger.info(Msg::BackupListed(backup));
+    }
+    logger.event(Event::Backups { backups });
+    ExitCode::Success.code()
 }
 
-fn init_logger(logger: &Logger) {
-    if CLI_ARGS.quiet {
-        logger.console_verbose("日志文件已关闭");
-        return;
+/// Adds a sink writing to a new log file, unless disabled by `--quiet`. Upon success, return the
+/// path of the log file.
+fn init_file_log(args: &CliArgs, logger: &mut Logger) -> Option<String> {
+    if args.quiet {
+        logger.verbose(Msg::FileLogDisabled);
+        return None;
     }
 
-    match log::init_logger(CLI_ARGS.quiet) {
-        Ok(file_name) => {
-            logger.console_verbose(format_args!("已创建日志文件 '{}'", file_name));
-            logger.file_info("文件日志开始记录");
+    let log_dir = Path::new(&args.log_dir);
+    let log_path = log::log_path(log_dir);
+    let sink = fs::create_dir_all(log_dir).and_then(|_| FileSink::create(&log_path, args.log_level()));
+    match sink {
+        Ok(sink) => logger.add(sink),
+        Err(err) => {
+            logger.warn(Msg::FileLogFailed(&err));
+            return None;
         }
-        Err(err) => logger.console_warn(format_args!("无法创建文件日志: {}", err)),
     }
+    let log_file = log_path.to_string_lossy().into_owned();
+    logger.verbose(Msg::FileLogCreated(&log_file));
+    logger.info(Msg::FileLogStarted);
+
+    let max_age = Duration::from_secs(args.log_max_age * 24 * 60 * 60);
+    match log::prune_logs(log_dir, &log_path, args.log_keep, max_age) {
+        Ok(0) => {}
+        Ok(count) => logger.verbose(Msg::LogsPruned(count)),
+        Err(err) => logger.warn(Msg::LogsPruneFailed(&err)),
+    }
+
+    Some(log_file)
 }
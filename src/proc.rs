@@ -1,17 +1,16 @@
 use core::time;
 use std::{
-    io,
-    path::Path,
-    process::{self, Command},
-    thread,
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    process::{self, Command, Output},
+    thread::{self, JoinHandle},
+    time::Instant,
 };
 
-#[cfg(windows)]
-use std::{env, fs};
-
-use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System};
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System, UpdateKind};
 
-use crate::{consts::CLI_ARGS, log::Logger};
+use crate::{event::Event, i18n::Msg, log::Logger, updater::UpdateOptions};
 
 /// Waits for the process with given PID to terminate. Returns true if the process terminates
 /// or has the same PID with this process.
@@ -20,7 +19,7 @@ pub fn wait_process(pid: u32, max_retries: usize, logger: &Logger) -> bool {
     let self_pid = Pid::from_u32(process::id());
 
     if self_pid == target_pid {
-        logger.batch_verbose("当前进程 ID 等于要等待的 ID, 推断进程已经继承");
+        logger.verbose(Msg::PidIsSelf);
         return true;
     }
 
@@ -33,22 +32,17 @@ pub fn wait_process(pid: u32, max_retries: usize, logger: &Logger) -> bool {
     for i in 0..max_retries {
         match sys.process(target_pid) {
             None => {
-                logger.batch_verbose(format_args!("进程 {} 已不存在, 推断已经结束", target_pid));
+                logger.verbose(Msg::ProcessGone(target_pid.as_u32()));
                 return true;
             }
             Some(process) => {
                 if process.pid() == self_pid {
-                    logger.batch_verbose("进程名称等于升级器名称, 推断进程已经继承");
+                    logger.verbose(Msg::ProcessIsSelf);
                     return true;
                 }
 
-                let name = process.name();
-                logger.batch_verbose(format_args!(
-                    "找到进程 {}, 尝试次数 {}/{}",
-                    name.to_string_lossy(),
-                    i + 1,
-                    max_retries
-                ));
+                let name = process.name().to_string_lossy();
+                logger.verbose(Msg::ProcessFound(&name, i + 1, max_retries));
 
                 sys.refresh_processes(processes_to_update, true);
                 thread::sleep(time::Duration::from_secs(1));
@@ -59,9 +53,88 @@ pub fn wait_process(pid: u32, max_retries: usize, logger: &Logger) -> bool {
     false
 }
 
+/// A process executing a file of the install root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunningExecutable {
+    pub pid: u32,
+    pub name: String,
+    /// Canonical path of the executable.
+    pub exe: PathBuf,
+}
+
+/// Returns the processes, other than this one, whose executable is in `root`.
+pub fn running_executables(root: &Path) -> Vec<RunningExecutable> {
+    let Ok(root) = fs::canonicalize(root) else {
+        return Vec::new();
+    };
+    let self_pid = Pid::from_u32(process::id());
+
+    let mut sys = System::new();
+    let refresh_kind = ProcessRefreshKind::nothing()
+        .with_exe(UpdateKind::OnlyIfNotSet)
+        .without_tasks();
+    sys.refresh_processes_specifics(ProcessesToUpdate::All, true, refresh_kind);
+    sys.processes()
+        .iter()
+        .filter(|(pid, _)| **pid != self_pid)
+        .filter_map(|(pid, process)| {
+            let exe = fs::canonicalize(process.exe()?).ok()?;
+            exe.starts_with(&root).then(|| RunningExecutable {
+                pid: pid.as_u32(),
+                name: process.name().to_string_lossy().into_owned(),
+                exe,
+            })
+        })
+        .collect()
+}
+
+/// Stops Milky processes bundled in the install root. Upon success, return the count of
+/// processes stopped.
+/// Runs `command` until it exits, killing it once `timeout` has elapsed. Whatever the caller
+/// piped from its stdout and stderr is read while it runs, so that a command printing more than
+/// a pipe holds does not block. Returns `None` if the command was killed.
+pub fn output_with_timeout(command: &mut Command, timeout: time::Duration) -> io::Result<Option<Output>> {
+    let mut child = command.spawn()?;
+    let stdout = child.stdout.take().map(read_to_end);
+    let stderr = child.stderr.take().map(read_to_end);
+
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if started.elapsed() > timeout {
+            _ = child.kill();
+            _ = child.wait();
+            // Processes it started may still hold the pipes open, so the readers are left behind.
+            return Ok(None);
+        }
+        thread::sleep(time::Duration::from_millis(50));
+    };
+
+    let join = |reader: Option<JoinHandle<io::Result<Vec<u8>>>>| match reader {
+        Some(reader) => reader.join().unwrap_or_else(|_| Ok(Vec::new())),
+        None => Ok(Vec::new()),
+    };
+    Ok(Some(Output {
+        status,
+        stdout: join(stdout)?,
+        stderr: join(stderr)?,
+    }))
+}
+
+/// Reads everything from `reader` on another thread.
+fn read_to_end<R: Read + Send + 'static>(mut reader: R) -> JoinHandle<io::Result<Vec<u8>>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf)?;
+        Ok(buf)
+    })
+}
+
 #[cfg(windows)]
-pub fn stop_local_yogurt(logger: &Logger) -> io::Result<usize> {
-    let target_path = env::current_dir()?.join("milky").join("yogurt.exe");
+pub fn stop_local_yogurt(options: &UpdateOptions, logger: &Logger) -> io::Result<usize> {
+    let target_path = options.root.join("milky").join("yogurt.exe");
     let target_path = match fs::canonicalize(&target_path) {
         Ok(path) => path,
         Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
@@ -80,12 +153,12 @@ pub fn stop_local_yogurt(logger: &Logger) -> io::Result<usize> {
         .collect();
 
     for pid in &pids {
-        logger.batch_warn(format_args!("发现仍在运行的内置 Milky 进程 {}, 尝试结束", pid));
+        logger.warn(Msg::MilkyFound(pid.as_u32()));
         let Some(process) = sys.process(*pid) else {
             continue;
         };
         if !process.kill() {
-            return Err(io::Error::other(format!("无法结束内置 Milky 进程 {}", pid)));
+            return Err(io::Error::other(Msg::MilkyKillFailed(pid.as_u32()).to_string()));
         }
     }
 
@@ -110,7 +183,7 @@ pub fn stop_local_yogurt(logger: &Logger) -> io::Result<usize> {
         .join(", ");
     Err(io::Error::new(
         io::ErrorKind::TimedOut,
-        format!("等待内置 Milky 进程退出超时: {}", remaining),
+        Msg::MilkyTimeout(&remaining).to_string(),
     ))
 }
 
@@ -120,17 +193,21 @@ fn is_same_windows_path(left: &Path, right: &Path) -> bool {
 }
 
 #[cfg(windows)]
-pub fn restart_sealdice(logger: &Logger) -> io::Result<()> {
-    if CLI_ARGS.skip_launch {
-        logger.batch_info("跳过重启主程序");
+pub fn restart_sealdice(options: &UpdateOptions, logger: &Logger) -> io::Result<()> {
+    if options.skip_launch {
+        logger.info(Msg::LaunchSkipped);
         return Ok(());
     }
 
-    logger.batch_info("3 秒后尝试重启主程序. 跨进程指令出现的错误可能不会被记录");
+    logger.info(Msg::LaunchScheduled);
     thread::sleep(time::Duration::from_secs(3));
 
-    let exe_path = Path::new("./").join(&CLI_ARGS.binary_name);
+    let exe_path = options.root.join(&options.binary_name);
+    logger.event(Event::Launching {
+        binary: options.binary_name.clone(),
+    });
     let mut command = Command::new(exe_path);
+    command.current_dir(&options.root);
 
     command.spawn().map(|_| ())
 }
@@ -158,41 +235,46 @@ mod tests {
 }
 
 #[cfg(unix)]
-pub fn restart_sealdice(logger: &Logger) -> io::Result<()> {
+pub fn restart_sealdice(options: &UpdateOptions, logger: &Logger) -> io::Result<()> {
     use std::{fs, os::unix::fs::PermissionsExt};
 
-    let exe_path = Path::new("./").join(&CLI_ARGS.binary_name);
+    let exe_path = options.root.join(&options.binary_name);
 
     if cfg!(target_os = "macos") {
         let output = Command::new("xattr")
-            .args(&["-rd", "com.apple.quarantine", CLI_ARGS.binary_name.as_str()])
+            .args(["-rd", "com.apple.quarantine"])
+            .arg(&exe_path)
             .output();
         match output {
-            Err(err) => logger.batch_warn(format_args!("未能除去可执行文件隔离属性, 运行可能出错: {}", err)),
+            Err(err) => logger.warn(Msg::QuarantineFailed(&err)),
             Ok(output) => {
                 if output.status.success() {
-                    logger.batch_success("成功除去可执行文件隔离属性");
+                    logger.success(Msg::QuarantineRemoved);
                 } else {
                     let err = String::from_utf8(output.stderr).unwrap_or(String::from("unknown"));
-                    logger.batch_warn(format_args!("未能除去可执行文件隔离属性, 运行可能出错: {}", err));
+                    logger.warn(Msg::QuarantineFailed(&err));
                 }
             }
         }
     }
 
     match fs::set_permissions(&exe_path, PermissionsExt::from_mode(0o755)) {
-        Ok(_) => logger.batch_info("成功设置可执行文件权限"),
-        Err(err) => logger.batch_warn(format_args!("设置可执行文件权限出错, 运行可能失败: {}", err)),
+        Ok(_) => logger.info(Msg::PermissionSet),
+        Err(err) => logger.warn(Msg::PermissionFailed(&err)),
     }
 
-    if CLI_ARGS.skip_launch {
-        logger.batch_info("跳过重启主程序");
+    if options.skip_launch {
+        logger.info(Msg::LaunchSkipped);
         return Ok(());
     }
 
-    logger.batch_info("3 秒后尝试重启主程序. 跨进程指令出现的错误可能不会被记录");
+    logger.info(Msg::LaunchScheduled);
     thread::sleep(time::Duration::from_secs(3));
 
+    logger.event(Event::Launching {
+        binary: options.binary_name.clone(),
+    });
     let mut command = Command::new(exe_path);
+    command.current_dir(&options.root);
     command.spawn().map(|_| ())
 }
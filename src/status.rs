@@ -0,0 +1,99 @@
+//! Defines the status file left in the install root for SealDice to read after restart.
+
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::Path,
+};
+
+use chrono::Local;
+use serde::Serialize;
+
+use crate::{event::Stage, exit_code::ExitCode};
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Success,
+    Failed,
+}
+
+/// The result of an update, as persisted to [`STATUS_FILE_NAME`](crate::consts::STATUS_FILE_NAME).
+#[derive(Serialize, Debug)]
+pub struct Status {
+    pub outcome: Outcome,
+    pub exit_code: ExitCode,
+    /// The stage at which the update was aborted, if it failed.
+    pub failed_stage: Option<Stage>,
+    pub error: Option<String>,
+    /// RFC 3339 timestamp of when the update started.
+    pub started_at: String,
+    /// RFC 3339 timestamp of when the update finished.
+    pub finished_at: String,
+    pub updater_version: &'static str,
+    pub package: String,
+    /// Hex-encoded SHA-256 of the package, if it could be read.
+    pub package_sha256: Option<String>,
+    /// Version of SealDice installed before the update, if it was queried.
+    pub from_version: Option<String>,
+    /// Version of SealDice in the package, as given by its manifest.
+    pub to_version: Option<String>,
+    pub entries: usize,
+    pub bytes: u64,
+    /// Paths of the protected entries that were not overwritten.
+    pub protected: Vec<String>,
+    /// Paths of the files replaced while a process was executing them, which must be restarted
+    /// to run the new version.
+    pub in_use: Vec<String>,
+    /// Count of obsolete entries moved into the backup directory.
+    pub removed: usize,
+    /// Path of the backup of the replaced and removed entries, relative to the install root, if
+    /// one was created.
+    pub backup: Option<String>,
+    pub warnings: Vec<String>,
+    /// Name of the log file of this update, if one was created.
+    pub log_file: Option<String>,
+}
+
+impl Status {
+    /// Creates a successful status starting now, to be filled in as the update proceeds.
+    pub fn new(package: &str) -> Status {
+        let now = Local::now().to_rfc3339();
+        Status {
+            outcome: Outcome::Success,
+            exit_code: ExitCode::Success,
+            failed_stage: None,
+            error: None,
+            started_at: now.clone(),
+            finished_at: now,
+            updater_version: env!("CARGO_PKG_VERSION"),
+            package: package.to_owned(),
+            package_sha256: None,
+            from_version: None,
+            to_version: None,
+            entries: 0,
+            bytes: 0,
+            protected: Vec::new(),
+            in_use: Vec::new(),
+            removed: 0,
+            backup: None,
+            warnings: Vec::new(),
+            log_file: None,
+        }
+    }
+
+    /// Writes the status as JSON to `path`. A temporary sibling file is written first and then
+    /// renamed over `path`, so that readers never observe a partially written status.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let mut temp_name = path.as_os_str().to_owned();
+        temp_name.push(".tmp");
+
+        let mut file = File::create(&temp_name)?;
+        serde_json::to_writer_pretty(&mut file, self)?;
+        file.write_all(b"\n")?;
+        file.sync_all()?;
+        drop(file);
+
+        fs::rename(&temp_name, path)
+    }
+}
@@ -0,0 +1,643 @@
+//! Defines the options of an update and the updater carrying it out.
+
+use std::{
+    fs, io,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use chrono::Local;
+use glob::Pattern;
+
+use crate::{
+    consts::{EXE_NAME, STATUS_FILE_NAME},
+    decompress::{
+        self, BackupSet, Conflict, ConflictPolicy, DecompressError, DirSync, Journal, Package, ProtectMode, ZipEncoding,
+    },
+    download::{self, Download},
+    event::{Event, Stage},
+    exit_code::ExitCode,
+    hook::{self, Hook, HookStage},
+    i18n::Msg,
+    log::Logger,
+    proc,
+    status::{Outcome, Status},
+    version,
+};
+
+/// The options of an update. Relative paths are resolved against the current directory.
+#[derive(Debug, Clone)]
+pub struct UpdateOptions {
+    /// The update package, or where to save it if `download` is present.
+    pub package: PathBuf,
+    /// If present, download the package before anything else.
+    pub download: Option<Download>,
+    /// The install root of SealDice, into which the package is decompressed.
+    pub root: PathBuf,
+    /// Name of the main executable in `root`.
+    pub binary_name: String,
+    /// If present, wait for the process with this PID to terminate before proceeding.
+    pub pid: Option<u32>,
+    /// How many times, once per second, to check whether the process given by `pid` has terminated.
+    pub wait_retries: usize,
+    /// Skip launching SealDice after updating.
+    pub skip_launch: bool,
+    /// Also remove the files written by the previous update that the package no longer contains.
+    pub prune: bool,
+    /// Existing entries matching any of these patterns are never overwritten. Defaults to
+    /// [`DEFAULT_PROTECTED`](decompress::DEFAULT_PROTECTED).
+    pub protect: Vec<Pattern>,
+    /// What to do with protected entries.
+    pub protect_mode: ProtectMode,
+    /// What to do with files of the package that are directories in `root`, or the other way
+    /// round.
+    pub conflict_policy: ConflictPolicy,
+    /// When to flush to disk the directories in which files are extracted.
+    pub dir_sync: DirSync,
+    /// Count of threads writing the files of a zip package, or zero for one per core.
+    pub jobs: usize,
+    /// Code page of the zip entry names that are not UTF-8.
+    pub zip_encoding: ZipEncoding,
+    /// Count of backups to keep in [`BACKUP_DIR`](crate::consts::BACKUP_DIR), including the one
+    /// of this update. Older backups are removed. Zero keeps every backup.
+    pub keep_backups: usize,
+    /// Commands to run at the stages of the update, after those given by the manifest.
+    pub hooks: Vec<Hook>,
+    /// How long each hook may run before it is killed and the update fails.
+    pub hook_timeout: Duration,
+    /// Name of the status file in `root`, or `None` to write no status file.
+    pub status_file: Option<String>,
+    /// Path of the log file of this update, recorded in the status file.
+    pub log_file: Option<String>,
+}
+
+impl UpdateOptions {
+    /// Creates the options to decompress `package` into the current directory, with every other
+    /// option set to its default.
+    pub fn new<P: Into<PathBuf>>(package: P) -> UpdateOptions {
+        UpdateOptions {
+            package: package.into(),
+            download: None,
+            root: PathBuf::from("."),
+            binary_name: String::from(EXE_NAME),
+            pid: None,
+            wait_retries: 30,
+            skip_launch: false,
+            prune: false,
+            protect: decompress::default_protected(),
+            protect_mode: ProtectMode::Skip,
+            conflict_policy: ConflictPolicy::Replace,
+            dir_sync: DirSync::Each,
+            jobs: 0,
+            zip_encoding: ZipEncoding::Cp936,
+            keep_backups: 5,
+            hooks: Vec::new(),
+            hook_timeout: Duration::from_secs(600),
+            status_file: Some(String::from(STATUS_FILE_NAME)),
+            log_file: None,
+        }
+    }
+
+    pub fn download(mut self, download: Option<Download>) -> UpdateOptions {
+        self.download = download;
+        self
+    }
+
+    pub fn root<P: Into<PathBuf>>(mut self, root: P) -> UpdateOptions {
+        self.root = root.into();
+        self
+    }
+
+    pub fn binary_name<S: Into<String>>(mut self, binary_name: S) -> UpdateOptions {
+        self.binary_name = binary_name.into();
+        self
+    }
+
+    pub fn pid(mut self, pid: Option<u32>) -> UpdateOptions {
+        self.pid = pid;
+        self
+    }
+
+    pub fn wait_retries(mut self, wait_retries: usize) -> UpdateOptions {
+        self.wait_retries = wait_retries;
+        self
+    }
+
+    pub fn skip_launch(mut self, skip_launch: bool) -> UpdateOptions {
+        self.skip_launch = skip_launch;
+        self
+    }
+
+    pub fn prune(mut self, prune: bool) -> UpdateOptions {
+        self.prune = prune;
+        self
+    }
+
+    /// Protects entries matching `pattern` in addition to those already protected.
+    pub fn protect(mut self, pattern: Pattern) -> UpdateOptions {
+        self.protect.push(pattern);
+        self
+    }
+
+    pub fn protect_mode(mut self, protect_mode: ProtectMode) -> UpdateOptions {
+        self.protect_mode = protect_mode;
+        self
+    }
+
+    pub fn conflict_policy(mut self, conflict_policy: ConflictPolicy) -> UpdateOptions {
+        self.conflict_policy = conflict_policy;
+        self
+    }
+
+    pub fn dir_sync(mut self, dir_sync: DirSync) -> UpdateOptions {
+        self.dir_sync = dir_sync;
+        self
+    }
+
+    pub fn jobs(mut self, jobs: usize) -> UpdateOptions {
+        self.jobs = jobs;
+        self
+    }
+
+    pub fn zip_encoding(mut self, zip_encoding: ZipEncoding) -> UpdateOptions {
+        self.zip_encoding = zip_encoding;
+        self
+    }
+
+    pub fn keep_backups(mut self, keep_backups: usize) -> UpdateOptions {
+        self.keep_backups = keep_backups;
+        self
+    }
+
+    /// Adds a hook, run after those already added at the same stage.
+    pub fn hook(mut self, hook: Hook) -> UpdateOptions {
+        self.hooks.push(hook);
+        self
+    }
+
+    pub fn hook_timeout(mut self, hook_timeout: Duration) -> UpdateOptions {
+        self.hook_timeout = hook_timeout;
+        self
+    }
+
+    pub fn status_file(mut self, status_file: Option<String>) -> UpdateOptions {
+        self.status_file = status_file;
+        self
+    }
+
+    pub fn log_file(mut self, log_file: Option<String>) -> UpdateOptions {
+        self.log_file = log_file;
+        self
+    }
+}
+
+/// Describes why and where an update was aborted.
+struct Failure {
+    stage: Stage,
+    kind: &'static str,
+    code: ExitCode,
+    message: String,
+}
+
+impl Failure {
+    fn new(stage: Stage, kind: &'static str, code: ExitCode, message: String) -> Failure {
+        Failure {
+            stage,
+            kind,
+            code,
+            message,
+        }
+    }
+}
+
+/// Carries out an update, reporting its progress to a [`Logger`].
+pub struct Updater<'a> {
+    options: UpdateOptions,
+    logger: &'a Logger,
+}
+
+impl<'a> Updater<'a> {
+    pub fn new(options: UpdateOptions, logger: &'a Logger) -> Updater<'a> {
+        Updater { options, logger }
+    }
+
+    pub fn options(&self) -> &UpdateOptions {
+        &self.options
+    }
+
+    /// Runs every stage of the update and writes the status file. The returned status tells
+    /// whether the update succeeded, and if not, at which stage it failed.
+    pub fn run(&self) -> Status {
+        let logger = self.logger;
+        let started = Instant::now();
+        let mut status = Status::new(&self.options.package.to_string_lossy());
+        status.log_file = self.options.log_file.clone();
+
+        let result = self.update(&mut status);
+        let exit_code = match &result {
+            Ok(()) => ExitCode::Success,
+            Err(failure) => failure.code,
+        };
+
+        match &result {
+            Ok(()) => logger.event(Event::Finished),
+            Err(failure) => {
+                logger.error(&failure.message);
+                logger.event(Event::Failed {
+                    stage: failure.stage,
+                    kind: failure.kind,
+                    exit_code: failure.code,
+                    message: failure.message.clone(),
+                });
+            }
+        }
+        logger.event(Event::Summary {
+            success: result.is_ok(),
+            exit_code,
+            entries: status.entries,
+            bytes: status.bytes,
+            elapsed_ms: started.elapsed().as_millis() as u64,
+        });
+
+        // A successful update wrote its status before launching SealDice.
+        if let Err(failure) = &result {
+            self.write_status(&mut status, Some(failure));
+        }
+
+        status
+    }
+
+    /// Completes the status with the outcome of the update and writes the status file.
+    fn write_status(&self, status: &mut Status, failure: Option<&Failure>) {
+        let logger = self.logger;
+        if let Some(failure) = failure {
+            status.outcome = Outcome::Failed;
+            status.exit_code = failure.code;
+            status.failed_stage = Some(failure.stage);
+            status.error = Some(failure.message.clone());
+        }
+        status.finished_at = Local::now().to_rfc3339();
+        status.warnings = logger.warnings();
+        if let Some(status_file) = &self.options.status_file
+            && let Err(err) = status.write(&self.options.root.join(status_file))
+        {
+            logger.warn(Msg::StatusWriteFailed(status_file, &err));
+        }
+    }
+
+    fn update(&self, status: &mut Status) -> Result<(), Failure> {
+        let logger = self.logger;
+        let mut options = self.options.clone();
+
+        if let Some(download) = &options.download
+            && let Err(err) = download::download(download, &options.package, logger)
+        {
+            return Err(Failure::new(
+                Stage::Download,
+                err.kind(),
+                err.exit_code(),
+                Msg::DownloadFailed(&err).to_string(),
+            ));
+        }
+
+        if let Some(pid) = options.pid {
+            logger.verbose(Msg::WaitingProcess(pid));
+            logger.event(Event::Waiting { pid });
+            if !proc::wait_process(pid, options.wait_retries, logger) {
+                return Err(Failure::new(
+                    Stage::Wait,
+                    "timeout",
+                    ExitCode::WaitTimeout,
+                    Msg::ProcessStillRunning.to_string(),
+                ));
+            }
+            logger.success(Msg::ProcessExited);
+            logger.event(Event::ProcessStopped { pid });
+        }
+
+        if decompress::is_unfinished(&options.root) {
+            logger.warn(Msg::Recovering);
+            decompress::recover(&options.root, logger).map_err(|err| {
+                Failure::new(
+                    Stage::Recover,
+                    "io",
+                    ExitCode::RecoverFailed,
+                    Msg::RecoverFailed(&err).to_string(),
+                )
+            })?;
+        }
+
+        let package = self.verify(&mut options, status).map_err(|err| {
+            Failure::new(
+                Stage::Verify,
+                err.kind(),
+                err.exit_code(),
+                Msg::VerifyFailed(&err).to_string(),
+            )
+        })?;
+        let options = &options;
+        let obsolete = decompress::obsolete_entries(options, &package, logger).map_err(|err| {
+            Failure::new(
+                Stage::Verify,
+                err.kind(),
+                err.exit_code(),
+                Msg::VerifyFailed(&err).to_string(),
+            )
+        })?;
+
+        self.run_hooks(options, HookStage::PreStop, status)?;
+
+        #[cfg(windows)]
+        match proc::stop_local_yogurt(options, logger) {
+            Ok(0) => logger.verbose(Msg::NoMilkyRunning),
+            Ok(count) => {
+                logger.success(Msg::MilkyStopped(count));
+                logger.event(Event::MilkyStopped { count });
+            }
+            Err(err) => {
+                return Err(Failure::new(
+                    Stage::StopMilky,
+                    "io",
+                    ExitCode::StopMilkyFailed,
+                    Msg::StopMilkyFailed(&err).to_string(),
+                ));
+            }
+        }
+
+        let journal = self.backup(options, status).map_err(|err| {
+            Failure::new(
+                Stage::Backup,
+                "io",
+                ExitCode::BackupFailed,
+                Msg::BackupFailed(&err).to_string(),
+            )
+        })?;
+        let files = match self.install(options, &package, &obsolete, &journal, status) {
+            Ok(files) => files,
+            Err(failure) => {
+                self.roll_back(options, journal, status);
+                return Err(failure);
+            }
+        };
+
+        if let Err(err) = decompress::write_file_list(&options.root, &files) {
+            logger.warn(Msg::FileListWriteFailed(&err));
+        }
+        let backup = journal.backup().name().to_owned();
+        match journal.finish(&options.root) {
+            // Until then, the backup and the package may still be needed to roll back or recover.
+            Ok(()) => {
+                self.remove_old_backups(options, &backup);
+                if options.download.is_some()
+                    && let Err(err) = fs::remove_file(&options.package)
+                {
+                    logger.warn(Msg::DownloadRemoveFailed(&err));
+                }
+            }
+            Err(err) => logger.warn(Msg::JournalFinishFailed(&err)),
+        }
+
+        self.run_hooks(options, HookStage::PreLaunch, status)?;
+        // SealDice reads the status file as it starts.
+        self.write_status(status, None);
+        if let Err(err) = proc::restart_sealdice(options, logger) {
+            return Err(Failure::new(
+                Stage::Restart,
+                "io",
+                ExitCode::RestartFailed,
+                Msg::RestartFailed(&err).to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Creates the backup of this update and starts its journal, then moves the main executable
+    /// into the backup.
+    fn backup(&self, options: &UpdateOptions, status: &mut Status) -> io::Result<Journal> {
+        let logger = self.logger;
+        let backup = BackupSet::create(&options.root, status)?;
+        let path = backup.rel_path();
+        status.backup = Some(path.clone());
+        let journal = Journal::begin(&options.root, &options.package, backup)?;
+
+        let existed = match decompress::backup_sealdice(options, journal.backup()) {
+            Ok(existed) => existed,
+            Err(err) => {
+                self.roll_back(options, journal, status);
+                return Err(err);
+            }
+        };
+        if existed {
+            logger.success(Msg::BackupDone(&path));
+        } else {
+            logger.info(Msg::BackupSkipped);
+        }
+        logger.event(Event::BackupDone { existed, path });
+        Ok(journal)
+    }
+
+    /// Removes the oldest backups beyond `options.keep_backups`, never the one of this update.
+    fn remove_old_backups(&self, options: &UpdateOptions, current: &str) {
+        match decompress::remove_old_backups(&options.root, options.keep_backups, current) {
+            Ok(0) => {}
+            Ok(count) => self.logger.verbose(Msg::BackupsRemoved(count)),
+            Err(err) => self.logger.warn(Msg::BackupsRemoveFailed(&err)),
+        }
+    }
+
+    /// Runs the post-backup hooks, writes every entry of the package into the install root,
+    /// removes the `obsolete` entries and runs the post-extract hooks, then records in the
+    /// journal that the new release is complete. Upon success, return the paths of the files
+    /// written.
+    fn install(
+        &self,
+        options: &UpdateOptions,
+        package: &Package,
+        obsolete: &[String],
+        journal: &Journal,
+        status: &mut Status,
+    ) -> Result<Vec<String>, Failure> {
+        let logger = self.logger;
+        self.run_hooks(options, HookStage::PostBackup, status)?;
+
+        let package_name = options.package.to_string_lossy();
+        logger.info(Msg::Decompressing(&package_name));
+        logger.event(Event::Decompressing {
+            package: package_name.into_owned(),
+        });
+        let result = decompress::decompress(options, package, journal, logger).map_err(|err| {
+            Failure::new(
+                Stage::Decompress,
+                err.kind(),
+                err.exit_code(),
+                Msg::DecompressFailed(&err).to_string(),
+            )
+        })?;
+
+        status.entries = result.entries;
+        status.bytes = result.bytes;
+        logger.success(Msg::Decompressed(result.entries));
+        logger.info(Msg::FilesChanged(result.created, result.updated, result.unchanged));
+        logger.event(Event::Decompressed {
+            entries: result.entries,
+            bytes: result.bytes,
+            protected: result.protected.len(),
+            created: result.created,
+            updated: result.updated,
+            unchanged: result.unchanged,
+        });
+        if !result.protected.is_empty() {
+            logger.info(Msg::EntriesProtected(
+                result.protected.len(),
+                &result.protected.join(", "),
+            ));
+        }
+        status.protected = result.protected;
+        if !result.in_use.is_empty() {
+            logger.warn(Msg::EntriesInUse(result.in_use.len(), &result.in_use.join(", ")));
+        }
+        status.in_use = result.in_use;
+
+        match decompress::prune(options, obsolete, journal.backup(), logger) {
+            Ok(0) => {}
+            Ok(count) => {
+                status.removed = count;
+                logger.success(Msg::Pruned(count));
+                logger.event(Event::Pruned { count });
+            }
+            Err(err) => {
+                return Err(Failure::new(
+                    Stage::Prune,
+                    "io",
+                    ExitCode::PruneFailed,
+                    Msg::PruneFailed(&err).to_string(),
+                ));
+            }
+        }
+        // A failing migration must still be able to roll the update back.
+        self.run_hooks(options, HookStage::PostExtract, status)?;
+
+        journal.extracted().map_err(|err| {
+            let err = DecompressError::from(err);
+            Failure::new(
+                Stage::Decompress,
+                err.kind(),
+                err.exit_code(),
+                Msg::DecompressFailed(&err).to_string(),
+            )
+        })?;
+        Ok(result.files)
+    }
+
+    /// Undoes the changes recorded in the journal of a failed update, which also removes its
+    /// backup. If that fails too, the journal is left for the next run to recover.
+    fn roll_back(&self, options: &UpdateOptions, journal: Journal, status: &mut Status) {
+        drop(journal);
+        match decompress::recover(&options.root, self.logger) {
+            Ok(_) => status.backup = None,
+            Err(err) => self.logger.warn(Msg::RollbackFailed(&err)),
+        }
+    }
+
+    fn run_hooks(&self, options: &UpdateOptions, stage: HookStage, status: &Status) -> Result<(), Failure> {
+        hook::run_hooks(options, stage, status, self.logger)
+            .map(|_| ())
+            .map_err(|err| {
+                Failure::new(
+                    Stage::Hook,
+                    "hook",
+                    ExitCode::HookFailed,
+                    Msg::HookFailed(&stage, &err).to_string(),
+                )
+            })
+    }
+
+    /// Inspects the package and checks it against its manifest, before anything is written.
+    /// The manifest may override the name of the main executable in `options`.
+    fn verify(&self, options: &mut UpdateOptions, status: &mut Status) -> Result<Package, DecompressError> {
+        let logger = self.logger;
+
+        match decompress::package_digest(&options.package) {
+            Ok(digest) => {
+                logger.verbose(Msg::PackageDigest(&digest));
+                status.package_sha256 = Some(digest);
+            }
+            Err(err) => logger.warn(Msg::PackageDigestFailed(&err)),
+        }
+
+        let mut package = decompress::inspect(&options.package, options.zip_encoding)?;
+        let required = package
+            .manifest
+            .as_ref()
+            .is_some_and(|manifest| manifest.min_previous_version.is_some());
+        let installed = match version::installed_version(&options.root, &options.binary_name) {
+            Ok(Some(installed)) => {
+                logger.info(Msg::InstalledVersion(&installed.to_string()));
+                status.from_version = Some(installed.to_string());
+                Some(installed)
+            }
+            Ok(None) if required => {
+                logger.warn(Msg::InstalledVersionUnknown);
+                None
+            }
+            Ok(None) => None,
+            Err(err) if required => {
+                logger.warn(Msg::InstalledVersionFailed(&err));
+                None
+            }
+            Err(err) => {
+                logger.verbose(Msg::InstalledVersionFailed(&err));
+                None
+            }
+        };
+
+        let Some(manifest) = &package.manifest else {
+            logger.info(Msg::ManifestMissing);
+            package.conflicts = check_conflicts(options, &package)?;
+            logger.event(Event::Verified {
+                version: None,
+                entries: package.entry_count(),
+            });
+            return Ok(package);
+        };
+
+        logger.info(Msg::ManifestFound(manifest.version.as_deref().unwrap_or("?")));
+        manifest.check_platform()?;
+        manifest.check_entries(&package.entries)?;
+
+        manifest.check_version(installed.as_ref())?;
+
+        if let Some(binary) = &manifest.binary
+            && *binary != options.binary_name
+        {
+            logger.info(Msg::BinaryFromManifest(binary));
+            options.binary_name = binary.clone();
+        }
+        options.hooks.splice(0..0, manifest.hooks.iter().cloned());
+
+        package.patched = decompress::apply_patches(options, &package, logger)?;
+        if !package.patched.is_empty() {
+            logger.success(Msg::PatchesApplied(package.patched.len()));
+        }
+
+        package.conflicts = check_conflicts(options, &package)?;
+        status.to_version = manifest.version.clone();
+        logger.success(Msg::Verified(package.entry_count()));
+        logger.event(Event::Verified {
+            version: manifest.version.clone(),
+            entries: package.entry_count(),
+        });
+        Ok(package)
+    }
+}
+
+/// Finds the paths of the install root whose type conflicts with the package, failing if the
+/// options abort on conflicts.
+fn check_conflicts(options: &UpdateOptions, package: &Package) -> Result<Vec<Conflict>, DecompressError> {
+    let conflicts = decompress::find_conflicts(options, package);
+    if options.conflict_policy == ConflictPolicy::Abort && !conflicts.is_empty() {
+        return Err(DecompressError::ConflictError(conflicts));
+    }
+    Ok(conflicts)
+}
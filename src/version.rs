@@ -0,0 +1,110 @@
+//! Parses SealDice versions and queries the version of an installed executable.
+
+use std::{
+    io,
+    path::Path,
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+pub use semver::Version;
+
+use crate::{i18n::Msg, proc};
+
+/// How long to wait for the installed executable to print its version.
+const VERSION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Parses a version leniently: a leading `v` is ignored, and a missing patch number is taken as
+/// zero, so that `v1.4` is read as `1.4.0`.
+pub fn parse_version(text: &str) -> Option<Version> {
+    let text = text.trim();
+    let text = text.strip_prefix(['v', 'V']).unwrap_or(text);
+    if let Ok(version) = Version::parse(text) {
+        return Some(version);
+    }
+
+    let (core, rest) = match text.find(['-', '+']) {
+        Some(index) => text.split_at(index),
+        None => (text, ""),
+    };
+    if core.split('.').count() == 2 {
+        return Version::parse(&format!("{}.0{}", core, rest)).ok();
+    }
+    None
+}
+
+/// Returns the first version found among the whitespace-separated words of `output`.
+pub fn find_version(output: &str) -> Option<Version> {
+    output
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .find_map(|word| parse_version(word.trim_matches(['(', ')', '"', '\''])))
+}
+
+/// Runs `binary --version` in `root` and returns the version it prints. Returns `None` if the
+/// executable does not exist.
+pub fn installed_version(root: &Path, binary_name: &str) -> io::Result<Option<Version>> {
+    let exe_path = root.join(binary_name);
+    if !exe_path.exists() {
+        return Ok(None);
+    }
+
+    let mut command = Command::new(&exe_path);
+    command
+        .arg("--version")
+        .current_dir(root)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null());
+    let Some(output) = proc::output_with_timeout(&mut command, VERSION_TIMEOUT)? else {
+        return Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            Msg::VersionTimeout(binary_name).to_string(),
+        ));
+    };
+    let output = String::from_utf8_lossy(&output.stdout);
+
+    find_version(&output).map(Some).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            Msg::VersionNotFound(binary_name).to_string(),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Version, find_version, installed_version, parse_version};
+
+    #[test]
+    fn versions_are_parsed_leniently() {
+        assert_eq!(parse_version("1.4.6"), Some(Version::new(1, 4, 6)));
+        assert_eq!(parse_version("v1.4"), Some(Version::new(1, 4, 0)));
+        assert_eq!(parse_version("1.5.0-dev").unwrap().pre.as_str(), "dev");
+        assert_eq!(parse_version("1.5-dev").unwrap().pre.as_str(), "dev");
+        assert_eq!(parse_version("SealDice"), None);
+    }
+
+    #[test]
+    fn version_is_found_in_output() {
+        assert_eq!(
+            find_version("SealDice v1.4.6 (linux/amd64)\n"),
+            Some(Version::new(1, 4, 6))
+        );
+        assert_eq!(find_version("unknown"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn version_is_read_from_output_larger_than_a_pipe() {
+        use std::{fs, os::unix::fs::PermissionsExt};
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let binary = dir.path().join("sealdice-core");
+        let script = "#!/bin/sh\nhead -c 200000 /dev/zero | tr '\\0' x\necho\necho 'SealDice v1.4.6'\n";
+        fs::write(&binary, script).unwrap();
+        fs::set_permissions(&binary, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let version = installed_version(dir.path(), "sealdice-core").unwrap();
+        assert_eq!(version, Some(Version::new(1, 4, 6)));
+    }
+}
@@ -0,0 +1,1031 @@
+use std::{
+    env,
+    fs::{self, File},
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    path::Path,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use glob::Pattern;
+use sealupd::{
+    UpdateOptions, Updater,
+    decompress::{self, ConflictPolicy, DirSync, ProtectMode, Recovery, ZipEncoding},
+    download::Download,
+    event::{Event, Stage},
+    exit_code::ExitCode,
+    hook::{Hook, HookStage},
+    log::{Level, Logger, MemorySink, Record},
+    status::Outcome,
+};
+use sha2::{Digest, Sha256};
+use tempfile::TempDir;
+use zip::{ZipWriter, write::SimpleFileOptions};
+
+fn write_zip(path: &Path, entries: &[(&str, &[u8])]) {
+    let mut writer = ZipWriter::new(File::create(path).unwrap());
+    for (name, content) in entries {
+        if name.ends_with('/') {
+            writer.add_directory(*name, SimpleFileOptions::default()).unwrap();
+        } else {
+            writer.start_file(*name, SimpleFileOptions::default()).unwrap();
+            writer.write_all(content).unwrap();
+        }
+    }
+    writer.finish().unwrap();
+}
+
+fn entry_extracted(index: usize, path: &str, bytes: u64) -> Event {
+    Event::EntryExtracted {
+        index,
+        total: 3,
+        path: path.to_owned(),
+        bytes,
+    }
+}
+
+fn options(dir: &TempDir) -> UpdateOptions {
+    UpdateOptions::new(dir.path().join("update.zip"))
+        .root(dir.path().join("root"))
+        .binary_name("sealdice-core")
+        .skip_launch(true)
+}
+
+#[test]
+fn updater_decompresses_into_root_and_backs_up_binary() {
+    let dir = TempDir::new().unwrap();
+    let options = options(&dir);
+    fs::create_dir_all(options.root.join("lib")).unwrap();
+    fs::write(options.root.join("sealdice-core"), b"old").unwrap();
+    fs::write(options.root.join("lib/a.js"), b"old a").unwrap();
+    write_zip(
+        &options.package,
+        &[("sealdice-core", b"new"), ("lib/", b""), ("lib/a.js", b"a")],
+    );
+
+    let sink = Arc::new(MemorySink::new());
+    let logger = Logger::new().with(sink.clone());
+    let status = Updater::new(options.clone(), &logger).run();
+
+    assert_eq!(status.outcome, Outcome::Success);
+    assert_eq!(status.entries, 3);
+    let backup = options.root.join(status.backup.as_deref().unwrap());
+    let events = sink.events();
+    assert_eq!(
+        events[..events.len() - 1],
+        [
+            Event::Verified {
+                version: None,
+                entries: 3
+            },
+            Event::BackupDone {
+                existed: true,
+                path: status.backup.clone().unwrap()
+            },
+            Event::Decompressing {
+                package: options.package.to_string_lossy().into_owned(),
+            },
+            entry_extracted(1, "sealdice-core", 3),
+            entry_extracted(2, "lib", 0),
+            entry_extracted(3, "lib/a.js", 1),
+            Event::Decompressed {
+                entries: 3,
+                bytes: 4,
+                protected: 0,
+                created: 0,
+                updated: 2,
+                unchanged: 0
+            },
+            Event::Finished,
+        ]
+    );
+    assert!(matches!(events.last(), Some(Event::Summary { success: true, .. })));
+    assert_eq!(fs::read(options.root.join("sealdice-core")).unwrap(), b"new");
+    assert_eq!(fs::read(options.root.join("lib/a.js")).unwrap(), b"a");
+    assert_eq!(fs::read(backup.join("sealdice-core")).unwrap(), b"old");
+    assert_eq!(fs::read(backup.join("lib/a.js")).unwrap(), b"old a");
+    assert!(options.root.join("sealupd-status.json").is_file());
+}
+
+#[test]
+fn updater_removes_downloaded_package_once_finished() {
+    let dir = TempDir::new().unwrap();
+    let zip = dir.path().join("served.zip");
+    write_zip(&zip, &[("sealdice-core", b"new")]);
+    let body = fs::read(&zip).unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/sealdice.zip", listener.local_addr().unwrap());
+    let sha256 = hex(&Sha256::digest(&body));
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        for line in BufReader::new(stream.try_clone().unwrap()).lines() {
+            if line.unwrap().is_empty() {
+                break;
+            }
+        }
+        let header = format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            body.len()
+        );
+        stream.write_all(header.as_bytes()).unwrap();
+        stream.write_all(&body).unwrap();
+    });
+
+    let mut options = options(&dir).download(Some(Download::new(url, sha256)));
+    options.package = dir.path().join(".sealupd-download/sealdice.zip");
+    fs::create_dir_all(&options.root).unwrap();
+    fs::write(options.root.join("sealdice-core"), b"old").unwrap();
+
+    let status = Updater::new(options.clone(), &Logger::new()).run();
+    assert_eq!(status.outcome, Outcome::Success, "{:?}", status.error);
+    assert_eq!(fs::read(options.root.join("sealdice-core")).unwrap(), b"new");
+    assert!(!options.package.exists());
+}
+
+#[test]
+fn updater_reports_missing_package() {
+    let dir = TempDir::new().unwrap();
+    let options = options(&dir);
+    fs::create_dir_all(&options.root).unwrap();
+
+    let sink = Arc::new(MemorySink::new());
+    let logger = Logger::new().with(sink.clone());
+    let status = Updater::new(options, &logger).run();
+
+    assert_eq!(status.outcome, Outcome::Failed);
+    assert_eq!(status.exit_code, ExitCode::DecompressIo);
+    assert!(sink.events().iter().any(|event| matches!(
+        event,
+        Event::Failed {
+            stage: Stage::Verify,
+            kind: "io",
+            exit_code: ExitCode::DecompressIo,
+            ..
+        }
+    )));
+}
+
+/// SealDice reads the status file as soon as it starts, so the launched binary here copies it.
+#[cfg(unix)]
+#[test]
+fn updater_writes_status_file_before_launching() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = TempDir::new().unwrap();
+    let options = options(&dir).skip_launch(false);
+    fs::create_dir_all(&options.root).unwrap();
+    let binary = options.root.join("sealdice-core");
+    fs::write(&binary, "#!/bin/sh\necho 'SealDice v1.4.6 (linux/amd64)'\n").unwrap();
+    fs::set_permissions(&binary, fs::Permissions::from_mode(0o755)).unwrap();
+    let manifest = format!(
+        r#"{{"version": "1.5.0", "os": "{}", "arch": "{}"}}"#,
+        env::consts::OS,
+        env::consts::ARCH
+    );
+    write_zip(
+        &options.package,
+        &[
+            ("sealupd-manifest.json", manifest.as_bytes()),
+            (
+                "sealdice-core",
+                b"#!/bin/sh\ncp sealupd-status.json seen.tmp && mv seen.tmp seen.json\n",
+            ),
+        ],
+    );
+
+    let status = Updater::new(options.clone(), &Logger::new()).run();
+    assert_eq!(status.outcome, Outcome::Success, "{:?}", status.error);
+
+    let seen = options.root.join("seen.json");
+    for _ in 0..100 {
+        if seen.exists() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    let seen: serde_json::Value = serde_json::from_slice(&fs::read(seen).unwrap()).unwrap();
+    assert_eq!(seen["outcome"], "success");
+    assert_eq!(seen["exit_code"], 0);
+    assert_eq!(seen["failed_stage"], serde_json::Value::Null);
+    assert_eq!(seen["from_version"], "1.4.6");
+    assert_eq!(seen["to_version"], "1.5.0");
+    assert_eq!(seen["entries"], 1);
+    assert_eq!(seen["backup"].as_str(), status.backup.as_deref());
+    let digest = hex(&Sha256::digest(fs::read(&options.package).unwrap()));
+    assert_eq!(seen["package_sha256"], digest);
+}
+
+#[test]
+fn updater_writes_status_file_of_failed_update() {
+    let dir = TempDir::new().unwrap();
+    let options = options(&dir);
+    fs::create_dir_all(&options.root).unwrap();
+    fs::write(options.root.join("sealdice-core"), b"old").unwrap();
+    let other_os = if env::consts::OS == "windows" { "linux" } else { "windows" };
+    let manifest = format!(r#"{{"version": "1.5.0", "os": "{}"}}"#, other_os);
+    write_zip(
+        &options.package,
+        &[
+            ("sealupd-manifest.json", manifest.as_bytes()),
+            ("sealdice-core", b"new"),
+        ],
+    );
+
+    let status = Updater::new(options.clone(), &Logger::new()).run();
+    assert_eq!(status.exit_code, ExitCode::WrongPlatform);
+
+    let written = fs::read(options.root.join("sealupd-status.json")).unwrap();
+    let written: serde_json::Value = serde_json::from_slice(&written).unwrap();
+    assert_eq!(written["outcome"], "failed");
+    assert_eq!(written["exit_code"], 34);
+    assert_eq!(written["failed_stage"], "verify");
+    assert!(written["error"].as_str().unwrap().contains(other_os));
+    assert_eq!(written["to_version"], serde_json::Value::Null);
+    assert_eq!(written["from_version"], serde_json::Value::Null);
+    assert_eq!(written["entries"], 0);
+    assert_eq!(written["backup"], serde_json::Value::Null);
+    assert_eq!(fs::read(options.root.join("sealdice-core")).unwrap(), b"old");
+}
+
+#[test]
+fn updater_refuses_package_for_other_platform() {
+    let dir = TempDir::new().unwrap();
+    let options = options(&dir);
+    fs::create_dir_all(&options.root).unwrap();
+    fs::write(options.root.join("sealdice-core"), b"old").unwrap();
+    let other_os = if env::consts::OS == "windows" { "linux" } else { "windows" };
+    let manifest = format!(r#"{{"version": "1.5.0", "os": "{}"}}"#, other_os);
+    write_zip(
+        &options.package,
+        &[
+            ("sealupd-manifest.json", manifest.as_bytes()),
+            ("sealdice-core", b"new"),
+        ],
+    );
+
+    let logger = Logger::new();
+    let status = Updater::new(options.clone(), &logger).run();
+
+    assert_eq!(status.exit_code, ExitCode::WrongPlatform);
+    assert_eq!(status.failed_stage, Some(Stage::Verify));
+    assert_eq!(fs::read(options.root.join("sealdice-core")).unwrap(), b"old");
+    assert_eq!(status.backup, None);
+    assert!(!options.root.join("backups").exists());
+}
+
+#[test]
+fn updater_checks_files_and_preserves_entries_listed_in_manifest() {
+    let dir = TempDir::new().unwrap();
+    let options = options(&dir);
+    fs::create_dir_all(options.root.join("data")).unwrap();
+    fs::write(options.root.join("data/config.yaml"), b"mine").unwrap();
+    let manifest = format!(
+        r#"{{
+            "version": "1.5.0",
+            "os": "{}",
+            "arch": "{}",
+            "binary": "sealdice",
+            "files": {{
+                "sealdice": "11507a0e2f5e69d5dfa40a62a1bd7b6ee57e6bcd85c67c9b8431b36fff21c437",
+                "data/config.yaml": "37a8eec1ce19687d132fe29051dca629d164e2c4958ba141d5f4133a33f0688f"
+            }},
+            "preserve": ["data/"]
+        }}"#,
+        env::consts::OS,
+        env::consts::ARCH
+    );
+    write_zip(
+        &options.package,
+        &[
+            ("sealupd-manifest.json", manifest.as_bytes()),
+            ("sealdice", b"new"),
+            ("data/config.yaml", b"default"),
+        ],
+    );
+
+    let logger = Logger::new();
+    let status = Updater::new(options.clone(), &logger).run();
+
+    assert_eq!(status.outcome, Outcome::Success, "{:?}", status.error);
+    assert_eq!(status.to_version.as_deref(), Some("1.5.0"));
+    assert_eq!(fs::read(options.root.join("sealdice")).unwrap(), b"new");
+    assert_eq!(fs::read(options.root.join("data/config.yaml")).unwrap(), b"mine");
+    assert!(!options.root.join("sealupd-manifest.json").exists());
+}
+
+#[test]
+fn updater_prunes_files_dropped_by_new_release() {
+    let dir = TempDir::new().unwrap();
+    let options = options(&dir).prune(true).keep_backups(1).dir_sync(DirSync::Batch);
+    fs::create_dir_all(&options.root).unwrap();
+    let logger = Logger::new();
+
+    write_zip(&options.package, &[("sealdice-core", b"v1"), ("lib/old.js", b"old")]);
+    let status = Updater::new(options.clone(), &logger).run();
+    assert_eq!(status.outcome, Outcome::Success);
+    assert_eq!(status.removed, 0);
+
+    write_zip(&options.package, &[("sealdice-core", b"v2"), ("lib/new.js", b"new")]);
+    let status = Updater::new(options.clone(), &logger).run();
+    assert_eq!(status.outcome, Outcome::Success);
+    assert_eq!(status.removed, 1);
+    assert!(!options.root.join("lib/old.js").exists());
+    let backup = options.root.join(status.backup.as_deref().unwrap());
+    assert_eq!(fs::read(backup.join("lib/old.js")).unwrap(), b"old");
+    assert_eq!(fs::read(backup.join("sealdice-core")).unwrap(), b"v1");
+    assert_eq!(decompress::list_backups(&options.root).unwrap().len(), 1);
+    assert_eq!(fs::read(options.root.join("lib/new.js")).unwrap(), b"new");
+}
+
+#[test]
+fn updater_never_deletes_directories_holding_protected_entries() {
+    let dir = TempDir::new().unwrap();
+    let options = options(&dir);
+    fs::create_dir_all(options.root.join("data/default")).unwrap();
+    fs::write(options.root.join("data/default/data.db"), b"mine").unwrap();
+    fs::write(options.root.join("old.js"), b"old").unwrap();
+    let manifest = r#"{"version": "1.5.0", "delete": ["data", "backups/", "old.js"]}"#;
+    write_zip(
+        &options.package,
+        &[
+            ("sealupd-manifest.json", manifest.as_bytes()),
+            ("sealdice-core", b"new"),
+        ],
+    );
+
+    let status = Updater::new(options.clone(), &Logger::new()).run();
+
+    assert_eq!(status.outcome, Outcome::Success, "{:?}", status.error);
+    assert_eq!(status.removed, 1);
+    assert!(!options.root.join("old.js").exists());
+    assert_eq!(fs::read(options.root.join("data/default/data.db")).unwrap(), b"mine");
+    let backup = options.root.join(status.backup.as_deref().unwrap());
+    assert!(!backup.join("data").exists());
+}
+
+#[test]
+fn updater_writes_protected_entries_aside() {
+    let dir = TempDir::new().unwrap();
+    let options = options(&dir)
+        .protect(Pattern::new("*.yaml").unwrap())
+        .protect_mode(ProtectMode::New);
+    fs::create_dir_all(options.root.join("data")).unwrap();
+    fs::write(options.root.join("data/save.db"), b"mine").unwrap();
+    fs::write(options.root.join("config.yaml"), b"mine").unwrap();
+    write_zip(
+        &options.package,
+        &[
+            ("data/save.db", b"default"),
+            ("data/new.db", b"default"),
+            ("config.yaml", b"default"),
+        ],
+    );
+
+    let logger = Logger::new();
+    let status = Updater::new(options.clone(), &logger).run();
+
+    assert_eq!(status.outcome, Outcome::Success);
+    assert_eq!(status.protected, ["data/save.db", "config.yaml"]);
+    assert_eq!(fs::read(options.root.join("data/save.db")).unwrap(), b"mine");
+    assert_eq!(fs::read(options.root.join("data/save.db.new")).unwrap(), b"default");
+    assert_eq!(fs::read(options.root.join("data/new.db")).unwrap(), b"default");
+    assert_eq!(fs::read(options.root.join("config.yaml")).unwrap(), b"mine");
+    assert_eq!(fs::read(options.root.join("config.yaml.new")).unwrap(), b"default");
+}
+
+#[test]
+fn updater_runs_hooks_and_aborts_on_failure() {
+    let dir = TempDir::new().unwrap();
+    let options = options(&dir).hook(Hook::new(HookStage::PostExtract, "echo migrated"));
+    fs::create_dir_all(&options.root).unwrap();
+    fs::write(options.root.join("sealdice-core"), b"old").unwrap();
+    let manifest = r#"{"hooks": [{"stage": "post_extract", "command": "echo from manifest > hook.txt"}]}"#;
+    write_zip(
+        &options.package,
+        &[
+            ("sealupd-manifest.json", manifest.as_bytes()),
+            ("sealdice-core", b"new"),
+        ],
+    );
+
+    let sink = Arc::new(MemorySink::new());
+    let logger = Logger::new().with(sink.clone());
+    let status = Updater::new(options.clone(), &logger).run();
+
+    assert_eq!(status.outcome, Outcome::Success);
+    assert!(options.root.join("hook.txt").is_file());
+    assert!(
+        sink.records()
+            .contains(&Record::Message(Level::Info, String::from("[hook] migrated")))
+    );
+    let commands: Vec<_> = sink
+        .events()
+        .into_iter()
+        .filter_map(|event| match event {
+            Event::HookFinished { command, .. } => Some(command),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(commands, ["echo from manifest > hook.txt", "echo migrated"]);
+
+    let options = options.hook(Hook::new(HookStage::PreStop, "exit 3"));
+    let status = Updater::new(options.clone(), &Logger::new()).run();
+
+    assert_eq!(status.exit_code, ExitCode::HookFailed);
+    assert_eq!(status.failed_stage, Some(Stage::Hook));
+    assert_eq!(fs::read(options.root.join("sealdice-core")).unwrap(), b"new");
+}
+
+#[cfg(unix)]
+#[test]
+fn updater_kills_hooks_that_run_too_long() {
+    let dir = TempDir::new().unwrap();
+    let options = options(&dir)
+        .hook(Hook::new(HookStage::PreStop, "sleep 30"))
+        .hook_timeout(Duration::from_secs(1));
+    fs::create_dir_all(&options.root).unwrap();
+    fs::write(options.root.join("sealdice-core"), b"old").unwrap();
+    write_zip(&options.package, &[("sealdice-core", b"new")]);
+
+    let started = Instant::now();
+    let status = Updater::new(options.clone(), &Logger::new()).run();
+
+    assert!(started.elapsed() < Duration::from_secs(10));
+    assert_eq!(status.exit_code, ExitCode::HookFailed);
+    assert_eq!(fs::read(options.root.join("sealdice-core")).unwrap(), b"old");
+}
+
+#[test]
+fn updater_rolls_back_when_post_extract_hook_fails() {
+    let dir = TempDir::new().unwrap();
+    let options = options(&dir).hook(Hook::new(HookStage::PostExtract, "exit 5"));
+    fs::create_dir_all(options.root.join("lib")).unwrap();
+    fs::write(options.root.join("sealdice-core"), b"old").unwrap();
+    fs::write(options.root.join("lib/a.js"), b"old a").unwrap();
+    fs::write(options.root.join("legacy.js"), b"legacy").unwrap();
+    write_zip(
+        &options.package,
+        &[
+            ("sealupd-manifest.json", br#"{"delete": ["legacy.js"]}"#),
+            ("sealdice-core", b"new"),
+            ("lib/a.js", b"a"),
+            ("lib/b.js", b"b"),
+        ],
+    );
+
+    let status = Updater::new(options.clone(), &Logger::new()).run();
+
+    assert_eq!(status.exit_code, ExitCode::HookFailed);
+    assert_eq!(status.failed_stage, Some(Stage::Hook));
+    assert_eq!(status.backup, None);
+    let root = &options.root;
+    assert_eq!(fs::read(root.join("sealdice-core")).unwrap(), b"old");
+    assert_eq!(fs::read(root.join("lib/a.js")).unwrap(), b"old a");
+    assert_eq!(fs::read(root.join("legacy.js")).unwrap(), b"legacy");
+    assert!(!root.join("lib/b.js").exists());
+    assert!(!root.join(sealupd::consts::JOURNAL_NAME).exists());
+}
+
+#[test]
+fn updater_applies_patch_against_matching_base() {
+    let dir = TempDir::new().unwrap();
+    let options = options(&dir);
+    fs::create_dir_all(&options.root).unwrap();
+    let base = b"SealDice 1.4.6 ".repeat(100);
+    let target = [b"SealDice 1.4.7 ".repeat(50), b"SealDice 1.4.6 ".repeat(50)].concat();
+    let patch = zstd::bulk::Compressor::with_dictionary(19, &base)
+        .unwrap()
+        .compress(&target)
+        .unwrap();
+    let manifest = format!(
+        r#"{{"patches": {{"sealdice-core": {{"sha256": "{}", "from": {{"{}": "patches/sealdice-core.zst"}}}}}}}}"#,
+        hex(&Sha256::digest(&target)),
+        hex(&Sha256::digest(&base))
+    );
+    write_zip(
+        &options.package,
+        &[
+            ("sealupd-manifest.json", manifest.as_bytes()),
+            ("patches/sealdice-core.zst", &patch),
+            ("lib/a.js", b"a"),
+        ],
+    );
+
+    fs::write(options.root.join("sealdice-core"), b"modified").unwrap();
+    let status = Updater::new(options.clone(), &Logger::new()).run();
+    assert_eq!(status.exit_code, ExitCode::PatchMismatch);
+    assert_eq!(fs::read(options.root.join("sealdice-core")).unwrap(), b"modified");
+    assert!(!options.root.join("lib/a.js").exists());
+
+    fs::write(options.root.join("sealdice-core"), &base).unwrap();
+    let status = Updater::new(options.clone(), &Logger::new()).run();
+    assert_eq!(status.outcome, Outcome::Success, "{:?}", status.error);
+    assert_eq!(status.entries, 2);
+    assert_eq!(fs::read(options.root.join("sealdice-core")).unwrap(), target);
+    let backup = options.root.join(status.backup.as_deref().unwrap());
+    assert_eq!(fs::read(backup.join("sealdice-core")).unwrap(), base);
+    assert!(!options.root.join("patches").exists());
+    assert!(!options.root.join(".sealupd-staging").exists());
+}
+
+#[test]
+fn updater_applies_several_patches_from_one_pass_over_a_tarball() {
+    let dir = TempDir::new().unwrap();
+    let mut options = options(&dir);
+    options.package = dir.path().join("update.tar.gz");
+    fs::create_dir_all(options.root.join("lib")).unwrap();
+
+    let mut patches = Vec::new();
+    let mut listed = Vec::new();
+    for (name, version) in [("sealdice-core", "1.4.7"), ("lib/a.js", "0.2.0")] {
+        let base = format!("{} 0.1.0 ", name).repeat(100).into_bytes();
+        let target = format!("{} {} ", name, version).repeat(100).into_bytes();
+        let patch = zstd::bulk::Compressor::with_dictionary(19, &base)
+            .unwrap()
+            .compress(&target)
+            .unwrap();
+        fs::write(options.root.join(name), &base).unwrap();
+        let entry = format!("patches/{}.zst", name.replace('/', "-"));
+        listed.push(format!(
+            r#""{}": {{"sha256": "{}", "from": {{"{}": "{}"}}}}"#,
+            name,
+            hex(&Sha256::digest(&target)),
+            hex(&Sha256::digest(&base)),
+            entry
+        ));
+        patches.push((name, entry, patch, target));
+    }
+    let manifest = format!(r#"{{"patches": {{{}}}}}"#, listed.join(", "));
+    let mut entries: Vec<(&str, &[u8])> = vec![("sealupd-manifest.json", manifest.as_bytes())];
+    entries.extend(
+        patches
+            .iter()
+            .map(|(_, entry, patch, _)| (entry.as_str(), patch.as_slice())),
+    );
+    write_tarball(&options.package, &entries);
+
+    let status = Updater::new(options.clone(), &Logger::new()).run();
+    assert_eq!(status.outcome, Outcome::Success, "{:?}", status.error);
+    for (name, _, _, target) in &patches {
+        assert_eq!(&fs::read(options.root.join(name)).unwrap(), target);
+    }
+}
+
+fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[test]
+fn updater_rolls_back_failed_update_and_recovers_unfinished_one() {
+    let dir = TempDir::new().unwrap();
+    let options = options(&dir);
+    fs::create_dir_all(&options.root).unwrap();
+    fs::write(options.root.join("sealdice-core"), b"old").unwrap();
+    write_zip(&options.package, &[("sealdice-core", b"new"), ("lib/a.js", b"a")]);
+
+    let failing = options.clone().hook(Hook::new(HookStage::PostBackup, "exit 3"));
+    let status = Updater::new(failing, &Logger::new()).run();
+    assert_eq!(status.exit_code, ExitCode::HookFailed);
+    assert_eq!(status.backup, None);
+    assert_eq!(fs::read(options.root.join("sealdice-core")).unwrap(), b"old");
+    assert!(!options.root.join("sealupd-journal.jsonl").exists());
+    assert!(decompress::list_backups(&options.root).unwrap().is_empty());
+
+    // An update killed halfway through leaves its journal behind.
+    let status = Updater::new(options.clone(), &Logger::new()).run();
+    assert_eq!(status.outcome, Outcome::Success);
+    let backup = status.backup.unwrap();
+    let journal = format!(
+        "{{\"step\":\"begin\",\"package\":\"update.zip\",\"backup\":\"{}\",\"started_at\":\"\"}}\n{{\"step\":\"write\",\"path\":\"lib/a.js\"}}\n",
+        backup.rsplit('/').next().unwrap()
+    );
+    fs::write(options.root.join("sealupd-journal.jsonl"), journal).unwrap();
+
+    let sink = Arc::new(MemorySink::new());
+    let logger = Logger::new().with(sink.clone());
+    let status = Updater::new(options.clone(), &logger).run();
+    assert_eq!(status.outcome, Outcome::Success, "{:?}", status.error);
+    assert!(sink.events().contains(&Event::Recovered {
+        recovery: Recovery::RolledBack {
+            removed: 1,
+            restored: 1
+        }
+    }));
+    assert_eq!(fs::read(options.root.join("sealdice-core")).unwrap(), b"new");
+    assert!(!options.root.join("sealupd-journal.jsonl").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn updater_keeps_the_mode_of_replaced_files() {
+    use std::os::unix::fs::PermissionsExt;
+
+    for jobs in [1, 2] {
+        let dir = TempDir::new().unwrap();
+        let options = options(&dir).jobs(jobs);
+        fs::create_dir_all(options.root.join("milky")).unwrap();
+        let helper = options.root.join("milky/helper");
+        let secret = options.root.join("secret.yaml");
+        fs::write(&helper, b"old helper").unwrap();
+        fs::set_permissions(&helper, fs::Permissions::from_mode(0o755)).unwrap();
+        fs::write(&secret, b"old").unwrap();
+        fs::set_permissions(&secret, fs::Permissions::from_mode(0o600)).unwrap();
+        write_zip(
+            &options.package,
+            &[("milky/helper", b"new helper"), ("secret.yaml", b"new")],
+        );
+
+        let status = Updater::new(options.clone(), &Logger::new()).run();
+
+        assert_eq!(status.outcome, Outcome::Success, "{:?}", status.error);
+        assert_eq!(fs::read(&helper).unwrap(), b"new helper");
+        let mode = |path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&helper), 0o755, "jobs {}", jobs);
+        assert_eq!(mode(&secret), 0o600, "jobs {}", jobs);
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn updater_replaces_running_executable_and_reports_it() {
+    use std::{os::unix::fs::PermissionsExt, process::Command, thread, time::Duration};
+
+    let dir = TempDir::new().unwrap();
+    let options = options(&dir);
+    fs::create_dir_all(options.root.join("milky")).unwrap();
+    let helper = options.root.join("milky/helper");
+    fs::copy("/bin/sleep", &helper).unwrap();
+    fs::set_permissions(&helper, fs::Permissions::from_mode(0o755)).unwrap();
+    let mut child = Command::new(&helper).arg("30").spawn().unwrap();
+    thread::sleep(Duration::from_millis(200));
+    write_zip(&options.package, &[("milky/helper", b"new helper")]);
+
+    let sink = Arc::new(MemorySink::new());
+    let logger = Logger::new().with(sink.clone());
+    let status = Updater::new(options.clone(), &logger).run();
+    child.kill().unwrap();
+    _ = child.wait();
+
+    assert_eq!(status.outcome, Outcome::Success, "{:?}", status.error);
+    assert_eq!(status.in_use, ["milky/helper"]);
+    assert!(sink.events().iter().any(|event| matches!(
+        event,
+        Event::EntryInUse { path, pid, .. } if path == "milky/helper" && *pid == child.id()
+    )));
+    assert_eq!(fs::read(&helper).unwrap(), b"new helper");
+    let mode = fs::metadata(&helper).unwrap().permissions().mode();
+    assert_eq!(mode & 0o111, 0o111, "the replaced helper is no longer executable");
+}
+
+/// The fixtures hold the same entries, `牌堆/中文牌堆.json` and `脚本/测试.js` among them, named
+/// in GBK without the UTF-8 flag as by archivers of Chinese Windows, or flagged as UTF-8.
+#[test]
+fn updater_decodes_gbk_and_utf8_entry_names() {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    for fixture in ["gbk-names.zip", "utf8-names.zip"] {
+        let dir = TempDir::new().unwrap();
+        let options = options(&dir);
+        fs::create_dir_all(&options.root).unwrap();
+        fs::copy(fixtures.join(fixture), &options.package).unwrap();
+
+        let status = Updater::new(options.clone(), &Logger::new()).run();
+        assert_eq!(status.outcome, Outcome::Success, "{}", fixture);
+        assert_eq!(
+            fs::read(options.root.join("牌堆/中文牌堆.json")).unwrap(),
+            br#"{"deck": []}"#
+        );
+        assert_eq!(fs::read(options.root.join("脚本/测试.js")).unwrap(), b"// test");
+    }
+
+    let dir = TempDir::new().unwrap();
+    let options = options(&dir).zip_encoding(ZipEncoding::Cp437);
+    fs::create_dir_all(&options.root).unwrap();
+    fs::copy(fixtures.join("gbk-names.zip"), &options.package).unwrap();
+    let status = Updater::new(options.clone(), &Logger::new()).run();
+    assert_eq!(status.outcome, Outcome::Success);
+    assert!(options.root.join("┼╞╢╤/╓╨╬─┼╞╢╤.json").is_file());
+}
+
+#[test]
+fn updater_refuses_manifest_binary_escaping_root() {
+    let dir = TempDir::new().unwrap();
+    let options = options(&dir);
+    fs::create_dir_all(&options.root).unwrap();
+    fs::write(dir.path().join("x"), b"outside").unwrap();
+    fs::write(options.root.join("sealdice-core"), b"old").unwrap();
+    write_zip(
+        &options.package,
+        &[
+            ("sealupd-manifest.json", br#"{"binary": "../x"}"#),
+            ("sealdice-core", b"new"),
+        ],
+    );
+
+    let status = Updater::new(options.clone(), &Logger::new()).run();
+    assert_eq!(status.exit_code, ExitCode::UnsafeEntry);
+    assert_eq!(fs::read(dir.path().join("x")).unwrap(), b"outside");
+    assert_eq!(fs::read(options.root.join("sealdice-core")).unwrap(), b"old");
+}
+
+#[test]
+fn updater_refuses_gbk_entry_escaping_root() {
+    let dir = TempDir::new().unwrap();
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/gbk-slip.zip");
+    let options = options(&dir);
+    fs::create_dir_all(&options.root).unwrap();
+    fs::copy(fixture, &options.package).unwrap();
+
+    let status = Updater::new(options.clone(), &Logger::new()).run();
+    assert_eq!(status.exit_code, ExitCode::UnsafeEntry);
+    assert!(!dir.path().join("逃逸.js").exists());
+}
+
+fn write_tarball(path: &Path, entries: &[(&str, &[u8])]) {
+    let encoder = flate2::write::GzEncoder::new(File::create(path).unwrap(), flate2::Compression::fast());
+    let mut builder = tar::Builder::new(encoder);
+    for (name, content) in entries {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, name, *content).unwrap();
+    }
+    builder.into_inner().unwrap().finish().unwrap();
+}
+
+#[test]
+fn updater_normalizes_backslash_separators() {
+    for tarball in [false, true] {
+        let dir = TempDir::new().unwrap();
+        let mut options = options(&dir);
+        fs::create_dir_all(&options.root).unwrap();
+        let entries: &[(&str, &[u8])] = &[("sealdice-core", b"new"), ("lib\\sub\\a.js", b"a")];
+        if tarball {
+            options.package = dir.path().join("update.tar.gz");
+            write_tarball(&options.package, entries);
+        } else {
+            write_zip(&options.package, entries);
+        }
+
+        let status = Updater::new(options.clone(), &Logger::new()).run();
+        assert_eq!(status.outcome, Outcome::Success);
+        assert_eq!(fs::read(options.root.join("lib/sub/a.js")).unwrap(), b"a");
+        assert!(!options.root.join("lib\\sub\\a.js").exists());
+    }
+
+    let dir = TempDir::new().unwrap();
+    let options = options(&dir);
+    fs::create_dir_all(&options.root).unwrap();
+    write_zip(&options.package, &[("lib\\..\\..\\evil.js", b"evil")]);
+
+    let status = Updater::new(options.clone(), &Logger::new()).run();
+    assert_eq!(status.exit_code, ExitCode::UnsafeEntry);
+    assert!(!dir.path().join("evil.js").exists());
+    assert!(!options.root.join("lib\\..\\..\\evil.js").exists());
+}
+
+#[test]
+fn updater_refuses_names_failing_on_other_platforms() {
+    let dir = TempDir::new().unwrap();
+    let options = options(&dir);
+    fs::create_dir_all(&options.root).unwrap();
+    fs::write(options.root.join("sealdice-core"), b"old").unwrap();
+    write_zip(
+        &options.package,
+        &[
+            ("sealdice-core", b"new"),
+            ("Data/x", b"1"),
+            ("data/x", b"2"),
+            ("aux.txt", b""),
+        ],
+    );
+
+    let sink = Arc::new(MemorySink::new());
+    let logger = Logger::new().with(sink.clone());
+    let status = Updater::new(options.clone(), &logger).run();
+
+    assert_eq!(status.exit_code, ExitCode::InvalidName);
+    assert!(sink.events().iter().any(|event| matches!(
+        event,
+        Event::Failed {
+            stage: Stage::Verify,
+            kind: "name",
+            ..
+        }
+    )));
+    assert_eq!(fs::read(options.root.join("sealdice-core")).unwrap(), b"old");
+    assert!(!options.root.join("data").exists());
+    assert!(!options.root.join("Data").exists());
+}
+
+#[test]
+fn updater_resolves_file_and_directory_conflicts_by_policy() {
+    for policy in [ConflictPolicy::Replace, ConflictPolicy::Skip, ConflictPolicy::Abort] {
+        let dir = TempDir::new().unwrap();
+        let options = options(&dir).conflict_policy(policy);
+        fs::create_dir_all(options.root.join("lib/x")).unwrap();
+        fs::write(options.root.join("lib/x/inner.js"), b"inner").unwrap();
+        fs::write(options.root.join("plugins"), b"old plugins").unwrap();
+        fs::write(options.root.join("sealdice-core"), b"old").unwrap();
+        write_zip(
+            &options.package,
+            &[("sealdice-core", b"new"), ("lib/x", b"x"), ("plugins/a.js", b"a")],
+        );
+
+        let sink = Arc::new(MemorySink::new());
+        let logger = Logger::new().with(sink.clone());
+        let status = Updater::new(options.clone(), &logger).run();
+
+        let resolved: Vec<_> = sink
+            .events()
+            .into_iter()
+            .filter(|event| matches!(event, Event::ConflictResolved { .. }))
+            .collect();
+        match policy {
+            ConflictPolicy::Replace => {
+                assert_eq!(status.outcome, Outcome::Success);
+                assert_eq!(resolved.len(), 2);
+                assert_eq!(fs::read(options.root.join("lib/x")).unwrap(), b"x");
+                assert_eq!(fs::read(options.root.join("plugins/a.js")).unwrap(), b"a");
+                let backup = options.root.join(status.backup.as_deref().unwrap());
+                assert_eq!(fs::read(backup.join("lib/x/inner.js")).unwrap(), b"inner");
+                assert_eq!(fs::read(backup.join("plugins")).unwrap(), b"old plugins");
+            }
+            ConflictPolicy::Skip => {
+                assert_eq!(status.outcome, Outcome::Success);
+                assert_eq!(resolved.len(), 2);
+                assert_eq!(fs::read(options.root.join("lib/x/inner.js")).unwrap(), b"inner");
+                assert_eq!(fs::read(options.root.join("plugins")).unwrap(), b"old plugins");
+                assert_eq!(fs::read(options.root.join("sealdice-core")).unwrap(), b"new");
+            }
+            ConflictPolicy::Abort => {
+                assert_eq!(status.exit_code, ExitCode::EntryConflict);
+                assert!(resolved.is_empty());
+                assert_eq!(fs::read(options.root.join("sealdice-core")).unwrap(), b"old");
+                assert!(options.root.join("lib/x/inner.js").is_file());
+            }
+        }
+    }
+}
+
+#[test]
+fn updater_never_replaces_conflicting_directories_holding_protected_entries() {
+    for policy in [ConflictPolicy::Replace, ConflictPolicy::Skip, ConflictPolicy::Abort] {
+        let dir = TempDir::new().unwrap();
+        let options = options(&dir).conflict_policy(policy);
+        fs::create_dir_all(options.root.join("data/default")).unwrap();
+        fs::write(options.root.join("data/default/data.db"), b"db").unwrap();
+        fs::write(options.root.join("sealdice-core"), b"old").unwrap();
+        write_zip(&options.package, &[("sealdice-core", b"new"), ("data", b"data")]);
+
+        let sink = Arc::new(MemorySink::new());
+        let logger = Logger::new().with(sink.clone());
+        let status = Updater::new(options.clone(), &logger).run();
+
+        assert_eq!(fs::read(options.root.join("data/default/data.db")).unwrap(), b"db");
+        if policy == ConflictPolicy::Abort {
+            assert_eq!(status.exit_code, ExitCode::EntryConflict);
+            continue;
+        }
+        assert_eq!(status.outcome, Outcome::Success);
+        assert_eq!(fs::read(options.root.join("sealdice-core")).unwrap(), b"new");
+        let backup = options.root.join(status.backup.as_deref().unwrap());
+        assert!(!backup.join("data").exists());
+        assert!(sink.events().iter().any(|event| matches!(
+            event,
+            Event::ConflictResolved { path, policy: ConflictPolicy::Skip, .. } if path == "data"
+        )));
+    }
+}
+
+#[test]
+fn updater_skips_unchanged_files() {
+    for tarball in [false, true] {
+        let dir = TempDir::new().unwrap();
+        let mut options = options(&dir);
+        fs::create_dir_all(options.root.join("lib")).unwrap();
+        fs::write(options.root.join("sealdice-core"), b"old").unwrap();
+        fs::write(options.root.join("lib/same.js"), b"same").unwrap();
+        fs::write(options.root.join("lib/size.js"), b"size").unwrap();
+        // The main executable is moved into the backup first, and compared with its copy there.
+        let binary: &[u8] = if tarball { b"old" } else { b"new" };
+        let entries: &[(&str, &[u8])] = &[
+            ("sealdice-core", binary),
+            ("lib/same.js", b"same"),
+            ("lib/size.js", b"SIZE"),
+            ("lib/new.js", b"new"),
+        ];
+        if tarball {
+            options.package = dir.path().join("update.tar.gz");
+            write_tarball(&options.package, entries);
+        } else {
+            write_zip(&options.package, entries);
+        }
+        let modified = fs::metadata(options.root.join("lib/same.js"))
+            .unwrap()
+            .modified()
+            .unwrap();
+
+        let sink = Arc::new(MemorySink::new());
+        let logger = Logger::new().with(sink.clone());
+        let status = Updater::new(options.clone(), &logger).run();
+
+        assert_eq!(status.outcome, Outcome::Success);
+        assert!(sink.events().contains(&Event::Decompressed {
+            entries: 4,
+            bytes: 10,
+            protected: 0,
+            created: 1,
+            updated: if tarball { 1 } else { 2 },
+            unchanged: if tarball { 2 } else { 1 },
+        }));
+        assert_eq!(fs::read(options.root.join("sealdice-core")).unwrap(), binary);
+        let same = options.root.join("lib/same.js");
+        assert_eq!(fs::metadata(&same).unwrap().modified().unwrap(), modified);
+        assert_eq!(fs::read(options.root.join("lib/size.js")).unwrap(), b"SIZE");
+        let backup = options.root.join(status.backup.as_deref().unwrap());
+        assert!(!backup.join("lib/same.js").exists());
+        assert!(backup.join("lib/size.js").exists());
+    }
+}
+
+#[test]
+fn updater_extracts_zip_in_parallel_with_ordered_progress() {
+    let names: Vec<String> = (0..200).map(|i| format!("assets/{}/{}.js", i % 7, i)).collect();
+    let mut entries: Vec<(&str, &[u8])> = vec![("sealdice-core", b"new")];
+    entries.extend(names.iter().map(|name| (name.as_str(), name.as_bytes())));
+
+    let mut runs = Vec::new();
+    for jobs in [1, 4] {
+        let dir = TempDir::new().unwrap();
+        let options = options(&dir).jobs(jobs);
+        fs::create_dir_all(options.root.join("assets/3")).unwrap();
+        fs::write(options.root.join("assets/3/3.js"), b"old").unwrap();
+        write_zip(&options.package, &entries);
+
+        let sink = Arc::new(MemorySink::new());
+        let logger = Logger::new().with(sink.clone());
+        let status = Updater::new(options.clone(), &logger).run();
+
+        assert_eq!(status.outcome, Outcome::Success);
+        for name in &names {
+            assert_eq!(fs::read(options.root.join(name)).unwrap(), name.as_bytes());
+        }
+        let backup = options.root.join(status.backup.as_deref().unwrap());
+        assert_eq!(fs::read(backup.join("assets/3/3.js")).unwrap(), b"old");
+        let extracted: Vec<_> = sink
+            .events()
+            .into_iter()
+            .filter(|event| matches!(event, Event::EntryExtracted { .. }))
+            .collect();
+        assert_eq!(extracted.len(), 201);
+        runs.push(extracted);
+    }
+    assert_eq!(runs[0], runs[1]);
+}
+
+#[test]
+fn updater_extracts_7z_packages() {
+    let dir = TempDir::new().unwrap();
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/sealdice.7z");
+    let mut options = options(&dir);
+    options.package = dir.path().join("update.7z");
+    fs::create_dir_all(options.root.join("lib")).unwrap();
+    fs::write(options.root.join("sealdice-core"), b"old").unwrap();
+    fs::write(options.root.join("lib/a.js"), b"a").unwrap();
+    fs::copy(fixture, &options.package).unwrap();
+
+    let sink = Arc::new(MemorySink::new());
+    let logger = Logger::new().with(sink.clone());
+    let status = Updater::new(options.clone(), &logger).run();
+
+    assert_eq!(status.outcome, Outcome::Success);
+    let root = &options.root;
+    assert_eq!(fs::read(root.join("sealdice-core")).unwrap(), b"new");
+    assert_eq!(fs::read(root.join("lib/a.js")).unwrap(), b"a");
+    assert_eq!(fs::read(root.join("lib/b.js")).unwrap(), b"b");
+    assert_eq!(fs::read(root.join("牌堆/中文牌堆.json")).unwrap(), br#"{"deck": []}"#);
+    let updater = root.join("new-updater").join(sealupd::consts::UPDATER_NAME);
+    assert_eq!(fs::read(updater).unwrap(), b"updater");
+    assert!(!root.join(sealupd::consts::UPDATER_NAME).exists());
+    assert!(sink.events().contains(&Event::Decompressed {
+        entries: 6,
+        bytes: 30,
+        protected: 0,
+        created: 4,
+        updated: 1,
+        unchanged: 1,
+    }));
+
+    let dir = TempDir::new().unwrap();
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/slip.7z");
+    let mut options = self::options(&dir);
+    options.package = dir.path().join("update.7z");
+    fs::create_dir_all(&options.root).unwrap();
+    fs::copy(fixture, &options.package).unwrap();
+
+    let status = Updater::new(options.clone(), &Logger::new()).run();
+    assert_eq!(status.exit_code, ExitCode::UnsafeEntry);
+    assert!(!dir.path().join("evil.js").exists());
+}